@@ -34,7 +34,8 @@
             (craneLibDefault.filterCargoSources path type)
             || (builtins.match ".*hue-openapi\.yaml$" path != null)
             || (builtins.match ".*/assets/.*$" path != null)
-            || (builtins.match ".*/tailwind\.css$" path != null);
+            || (builtins.match ".*/tailwind\.css$" path != null)
+            || (builtins.match ".*/migrations/.*$" path != null);
         };
 
         # Helper function to build huebot and its docker image for a given hostPkgs
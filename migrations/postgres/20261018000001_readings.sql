@@ -0,0 +1,12 @@
+-- Sensor readings captured by huebot's history recorder, keyed by v2 resource id
+create table if not exists huebot_readings (
+    resource_id text not null,
+    device_id text not null,
+    metric text not null,
+    recorded_at timestamptz not null,
+    value double precision not null,
+    primary key (resource_id, recorded_at)
+);
+
+create index if not exists huebot_readings_device_idx
+    on huebot_readings (device_id, metric, recorded_at);
@@ -0,0 +1,49 @@
+-- Maps the bridge's v1 sensor ids (the trailing number of `id_v1`) to v2 resource ids
+create table if not exists huebot_v1_ids (
+    resource_id text primary key,
+    v1_id integer not null
+);
+
+create index if not exists huebot_v1_ids_v1_idx on huebot_v1_ids (v1_id);
+
+-- The history functions read by the Graphs page when the recorder is off. Databases filled by an
+-- external logger already define them, so they are only created where missing.
+do $$
+begin
+    if not exists (select 1 from pg_proc where proname = 'sensor_motion') then
+        create function sensor_motion(sensor_id integer, start_time timestamptz, end_time timestamptz)
+        returns table (creationtime timestamp, motion boolean)
+        language sql stable as $f$
+            select r.recorded_at at time zone 'UTC', r.value <> 0
+            from huebot_readings r
+            join huebot_v1_ids i on i.resource_id = r.resource_id
+            where i.v1_id = sensor_id and r.recorded_at between start_time and end_time
+            order by r.recorded_at
+        $f$;
+    end if;
+
+    if not exists (select 1 from pg_proc where proname = 'sensor_temperature') then
+        create function sensor_temperature(sensor_id integer, start_time timestamptz, end_time timestamptz)
+        returns table (creationtime timestamp, temperature real)
+        language sql stable as $f$
+            select r.recorded_at at time zone 'UTC', r.value::real
+            from huebot_readings r
+            join huebot_v1_ids i on i.resource_id = r.resource_id
+            where i.v1_id = sensor_id and r.recorded_at between start_time and end_time
+            order by r.recorded_at
+        $f$;
+    end if;
+
+    if not exists (select 1 from pg_proc where proname = 'sensor_light_level') then
+        create function sensor_light_level(sensor_id integer, start_time timestamptz, end_time timestamptz)
+        returns table (creationtime timestamp, light_level integer)
+        language sql stable as $f$
+            select r.recorded_at at time zone 'UTC', r.value::integer
+            from huebot_readings r
+            join huebot_v1_ids i on i.resource_id = r.resource_id
+            where i.v1_id = sensor_id and r.recorded_at between start_time and end_time
+            order by r.recorded_at
+        $f$;
+    end if;
+end
+$$;
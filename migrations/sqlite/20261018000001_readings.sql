@@ -0,0 +1,13 @@
+-- Sensor readings captured by huebot's history recorder, keyed by v2 resource id.
+-- Times are milliseconds since the Unix epoch, so they sort and compare numerically.
+create table if not exists huebot_readings (
+    resource_id text not null,
+    device_id text not null,
+    metric text not null,
+    recorded_at integer not null,
+    value real not null,
+    primary key (resource_id, recorded_at)
+);
+
+create index if not exists huebot_readings_device_idx
+    on huebot_readings (device_id, metric, recorded_at);
@@ -0,0 +1,8 @@
+-- Maps the bridge's v1 sensor ids (the trailing number of `id_v1`) to v2 resource ids. SQLite has
+-- no stored functions, so v1 lookups join this table directly.
+create table if not exists huebot_v1_ids (
+    resource_id text primary key,
+    v1_id integer not null
+);
+
+create index if not exists huebot_v1_ids_v1_idx on huebot_v1_ids (v1_id);
@@ -0,0 +1,22 @@
+-- Summaries of huebot_readings at coarser resolutions (in seconds), kept after raw readings expire.
+-- For motion each value is the fraction of the bucket with motion detected. Times are milliseconds
+-- since the Unix epoch, as in huebot_readings.
+create table if not exists huebot_rollups (
+    resolution integer not null,
+    resource_id text not null,
+    device_id text not null,
+    metric text not null,
+    bucket_start integer not null,
+    min_value real not null,
+    avg_value real not null,
+    max_value real not null,
+    primary key (resolution, resource_id, bucket_start)
+);
+
+-- How far each resource has been rolled up at each resolution
+create table if not exists huebot_rollup_watermarks (
+    resolution integer not null,
+    resource_id text not null,
+    rolled_up_to integer not null,
+    primary key (resolution, resource_id)
+);
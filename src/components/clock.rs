@@ -1,8 +1,10 @@
+use crate::preferences::use_preferences;
 use dioxus::prelude::*;
 
 #[component]
 pub fn Clock() -> Element {
-    let mut now = use_signal(|| chrono::Local::now().format("%H:%M:%S").to_string());
+    let prefs = use_preferences();
+    let mut now = use_signal(chrono::Utc::now);
 
     use_future(move || async move {
         loop {
@@ -11,11 +13,13 @@ pub fn Clock() -> Element {
             #[cfg(not(feature = "server"))]
             gloo_timers::future::sleep(std::time::Duration::from_secs(1)).await;
 
-            now.set(chrono::Local::now().format("%H:%M:%S").to_string());
+            now.set(chrono::Utc::now());
         }
     });
 
+    let time = prefs().format_time(now());
+
     rsx! {
-        span { class: "text-lg text-gray-500 font-mono", "Time: {now}" }
+        span { class: "text-lg text-gray-500 font-mono", "Time: {time}" }
     }
 }
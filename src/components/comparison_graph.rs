@@ -0,0 +1,282 @@
+use crate::components::sensor_data_graph::time_ticks;
+use crate::components::HistoryPoint;
+use crate::preferences::use_preferences;
+use chrono::{DateTime, Utc};
+use dioxus::prelude::*;
+
+/// One line of a [`ComparisonGraph`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphSeries {
+    pub name: String,
+    pub color: String,
+    pub history: Vec<HistoryPoint>,
+}
+
+/// Formats an axis or tooltip value, shortening thousands
+fn format_value(v: f64, unit: &str) -> String {
+    if v.abs() >= 1000.0 {
+        format!("{:.1}k{}", v / 1000.0, unit)
+    } else if v.abs() >= 10.0 {
+        format!("{:.0}{}", v, unit)
+    } else {
+        format!("{:.1}{}", v, unit)
+    }
+}
+
+/// Plots several series across `start..end` on shared axes, with a legend. Hovering shows every
+/// series' value at that time.
+#[component]
+pub fn ComparisonGraph(
+    series: Vec<GraphSeries>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    #[props(default = 1000)] width: u32,
+    #[props(default = 300)] height: u32,
+    #[props(default = String::new())] unit: String,
+    #[props(default = false)] log_scale: bool,
+) -> Element {
+    let mut hovered_time = use_signal(|| None::<DateTime<Utc>>);
+    let prefs = use_preferences()();
+
+    if series.iter().all(|s| s.history.is_empty()) {
+        return rsx! {
+            div { class: "h-full w-full flex items-center justify-center text-gray-400 italic", "No data available" }
+        };
+    }
+
+    let total = (end - start).num_seconds().max(1) as f64;
+    let x_scale = move |t: DateTime<Utc>| {
+        let elapsed = (t - start).num_seconds() as f64;
+        (elapsed / total * width as f64).clamp(0.0, width as f64)
+    };
+
+    let to_axis = move |v: f64| if log_scale { v.max(0.01).log10() } else { v };
+    let from_axis = move |v: f64| if log_scale { 10f64.powf(v) } else { v };
+
+    let (min_v, max_v) = {
+        let (min, max) = series
+            .iter()
+            .flat_map(|s| s.history.iter().map(|p| to_axis(p.value)))
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+                (min.min(v), max.max(v))
+            });
+        if (max - min).abs() < 0.1 {
+            (min - 1.0, max + 1.0)
+        } else {
+            let padding = (max - min) * 0.1;
+            (min - padding, max + padding)
+        }
+    };
+    let y_scale = move |v: f64| {
+        let v = to_axis(v);
+        height as f64 - ((v - min_v) / (max_v - min_v) * height as f64).clamp(0.0, height as f64)
+    };
+
+    let paths: Vec<(String, String)> = series
+        .iter()
+        .map(|s| {
+            let points: Vec<String> = s
+                .history
+                .iter()
+                .map(|p| format!("{} {}", x_scale(p.time), y_scale(p.value)))
+                .collect();
+            let d = if points.is_empty() {
+                String::new()
+            } else {
+                format!("M {}", points.join(" L "))
+            };
+            (d, s.color.clone())
+        })
+        .collect();
+
+    // Hover regions span halfway to the neighbouring times of any series
+    let mut times: Vec<DateTime<Utc>> = series
+        .iter()
+        .flat_map(|s| s.history.iter().map(|p| p.time))
+        .collect();
+    times.sort();
+    times.dedup();
+    let hit_regions: Vec<(f64, f64, DateTime<Utc>)> = times
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            let x = x_scale(*t);
+            let start_x = match i {
+                0 => 0.0,
+                i => (x_scale(times[i - 1]) + x) / 2.0,
+            };
+            let end_x = match times.get(i + 1) {
+                Some(next) => (x + x_scale(*next)) / 2.0,
+                None => width as f64,
+            };
+            (start_x, end_x, *t)
+        })
+        .collect();
+
+    // Each series' latest value at the hovered time
+    let hovered_values: Vec<(String, String, Option<f64>)> = match hovered_time() {
+        Some(t) => series
+            .iter()
+            .map(|s| {
+                let value = s.history.iter().take_while(|p| p.time <= t).last().map(|p| p.value);
+                (s.name.clone(), s.color.clone(), value)
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let label_items = time_ticks(&prefs, start, end);
+    let y_labels: Vec<(f64, f64)> = (0..=4)
+        .map(|i| {
+            let pct = (i as f64 / 4.0) * 100.0;
+            (pct, from_axis(max_v - (i as f64 / 4.0) * (max_v - min_v)))
+        })
+        .collect();
+
+    rsx! {
+        div { class: "flex flex-col w-full h-full",
+            div { class: "relative w-full flex-1 min-h-0 pt-2 pb-8 pl-2 pr-12",
+                svg {
+                    width: "100%",
+                    height: "100%",
+                    view_box: "0 0 {width} {height}",
+                    preserve_aspect_ratio: "none",
+                    class: "overflow-visible",
+                    onmouseleave: move |_| hovered_time.set(None),
+
+                    for i in 0..=4 {
+                        {
+                            let y = (height as f64 / 4.0) * i as f64;
+                            rsx! {
+                                line {
+                                    x1: "0", y1: "{y}", x2: "{width}", y2: "{y}",
+                                    stroke: "currentColor", stroke_width: "0.5", class: "text-gray-200 dark:text-gray-700",
+                                    vector_effect: "non-scaling-stroke"
+                                }
+                            }
+                        }
+                    }
+
+                    for (d, color) in paths {
+                        path {
+                            d: "{d}",
+                            fill: "none",
+                            stroke: "{color}",
+                            stroke_width: "2",
+                            stroke_linejoin: "round",
+                            stroke_linecap: "round",
+                            vector_effect: "non-scaling-stroke"
+                        }
+                    }
+
+                    for (pct, _, _) in &label_items {
+                        {
+                            let x = (*pct / 100.0) * width as f64;
+                            rsx! {
+                                line {
+                                    x1: "{x}", y1: "{height}", x2: "{x}", y2: "{height + 5}",
+                                    stroke: "currentColor", stroke_width: "1.5", class: "text-gray-300 dark:text-gray-200",
+                                    vector_effect: "non-scaling-stroke"
+                                }
+                            }
+                        }
+                    }
+
+                    g {
+                        for (start_x, end_x, t) in hit_regions {
+                            rect {
+                                x: "{start_x}",
+                                y: "0",
+                                width: "{end_x - start_x}",
+                                height: "{height}",
+                                fill: "transparent",
+                                style: "pointer-events: all",
+                                onmouseenter: move |_| hovered_time.set(Some(t)),
+                            }
+                        }
+                    }
+
+                    if let Some(t) = hovered_time() {
+                        {
+                            let x = x_scale(t);
+                            rsx! {
+                                line {
+                                    x1: "{x}", y1: "0", x2: "{x}", y2: "{height}",
+                                    stroke: "currentColor",
+                                    stroke_width: "1",
+                                    stroke_dasharray: "4 2",
+                                    class: "text-gray-400",
+                                    vector_effect: "non-scaling-stroke"
+                                }
+                                for (_, color, value) in hovered_values.iter().cloned() {
+                                    if let Some(v) = value {
+                                        circle {
+                                            cx: "{x}", cy: "{y_scale(v)}", r: "4",
+                                            fill: "{color}",
+                                            stroke: "white",
+                                            stroke_width: "2"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                div { class: "absolute bottom-0 left-2 right-12 h-6 pointer-events-none",
+                    for (pct, major, minor) in label_items {
+                        span {
+                            class: "absolute text-xs font-bold text-gray-400 dark:text-white whitespace-nowrap",
+                            style: "left: {pct}%; transform: translateX(-50%);",
+                            span { "{major}" }
+                            span { class: "hidden sm:inline", "{minor}" }
+                        }
+                    }
+                }
+
+                div { class: "absolute top-2 bottom-8 right-0 w-12 pointer-events-none",
+                    for (pct, val) in y_labels {
+                        span {
+                            class: "absolute right-2 text-[10px] font-bold text-gray-400 dark:text-white whitespace-nowrap",
+                            style: "top: {pct}%; transform: translateY(-50%);",
+                            "{format_value(val, &unit)}"
+                        }
+                    }
+                }
+
+                if let Some(t) = hovered_time() {
+                    {
+                        let x_pct = (x_scale(t) / width as f64) * 100.0;
+                        let transform = if x_pct > 80.0 { "translateX(-100%)" } else { "translateX(-50%)" };
+                        let time_str = format!("{} {}", prefs.format_date(t), prefs.format_short_time(t));
+                        rsx! {
+                            div {
+                                class: "absolute top-2 z-10 pointer-events-none bg-white dark:bg-gray-800 rounded shadow-lg p-2 border border-gray-200 dark:border-gray-700 text-xs",
+                                style: "left: {x_pct}%; transform: {transform};",
+                                div { class: "font-bold text-gray-700 dark:text-gray-200 whitespace-nowrap", "{time_str}" }
+                                for (name, color, value) in hovered_values.iter().cloned() {
+                                    div { class: "flex items-center gap-2 text-gray-600 dark:text-gray-400 whitespace-nowrap",
+                                        span { class: "inline-block w-2 h-2 rounded-full", style: "background: {color};" }
+                                        span { "{name}" }
+                                        span { class: "ml-auto pl-2 font-semibold",
+                                            {value.map(|v| format_value(v, &unit)).unwrap_or_else(|| "–".to_string())}
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            div { class: "flex flex-wrap gap-x-4 gap-y-1 pt-2 text-sm",
+                for s in series.iter() {
+                    span { class: "flex items-center gap-2",
+                        span { class: "inline-block w-3 h-1 rounded", style: "background: {s.color};" }
+                        "{s.name}"
+                    }
+                }
+            }
+        }
+    }
+}
@@ -13,8 +13,17 @@ pub use sparkline::{HistoryPoint, Sparkline};
 mod sensor_data_graph;
 pub use sensor_data_graph::SensorDataGraph;
 
+mod comparison_graph;
+pub use comparison_graph::{ComparisonGraph, GraphSeries};
+
 mod activity;
 pub use activity::ActivityIndicator;
 
 mod pulse;
 pub use pulse::Pulsing;
+
+mod sensor_settings;
+pub use sensor_settings::SensorSettingsForm;
+
+mod range_picker;
+pub use range_picker::RangePicker;
@@ -0,0 +1,85 @@
+use crate::hue::aggregate::GraphRange;
+use crate::preferences::use_preferences;
+use crate::Route;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use dioxus::prelude::*;
+
+/// Preset range links plus inputs for a custom range, in the preferred time zone. `to` gives the
+/// route showing a range.
+#[component]
+pub fn RangePicker(range: GraphRange, to: Callback<GraphRange, Route>) -> Element {
+    let prefs = use_preferences()();
+    let nav = navigator();
+
+    let (start, end) = range.bounds(Utc::now());
+    let input_value = {
+        let prefs = prefs.clone();
+        move |t: DateTime<Utc>| prefs.localize(t).format("%Y-%m-%dT%H:%M").to_string()
+    };
+    let mut custom_start = use_signal({
+        let input_value = input_value.clone();
+        move || input_value(start)
+    });
+    let mut custom_end = use_signal(move || input_value(end));
+    let mut error = use_signal(|| None::<String>);
+
+    let apply = move |_: Event<MouseData>| {
+        let parse = |s: String| {
+            NaiveDateTime::parse_from_str(&s, "%Y-%m-%dT%H:%M")
+                .ok()
+                .and_then(|t| prefs.to_utc(t))
+        };
+        match (parse(custom_start()), parse(custom_end())) {
+            (Some(start), Some(end)) if start < end => {
+                error.set(None);
+                nav.push(to.call(GraphRange::Custom { start, end }));
+            }
+            _ => error.set(Some("The start must be before the end".to_string())),
+        }
+    };
+
+    let input_class = "p-1 rounded border border-gray-300 dark:border-gray-600 dark:bg-gray-900 text-sm";
+
+    rsx! {
+        div { class: "flex flex-wrap items-center gap-2 mb-6",
+            for preset in GraphRange::PRESETS {
+                Link {
+                    to: to.call(preset),
+                    class: if preset == range {
+                        "px-3 py-1 rounded bg-blue-600 text-white text-sm"
+                    } else {
+                        "px-3 py-1 rounded bg-gray-100 dark:bg-gray-800 hover:bg-gray-200 dark:hover:bg-gray-700 text-sm"
+                    },
+                    "{preset}"
+                }
+            }
+            div { class: "flex items-center gap-2 ml-auto",
+                input {
+                    class: input_class,
+                    r#type: "datetime-local",
+                    value: "{custom_start}",
+                    oninput: move |e| custom_start.set(e.value()),
+                }
+                span { class: "text-gray-500", "–" }
+                input {
+                    class: input_class,
+                    r#type: "datetime-local",
+                    value: "{custom_end}",
+                    oninput: move |e| custom_end.set(e.value()),
+                }
+                button {
+                    class: if matches!(range, GraphRange::Custom { .. }) {
+                        "px-3 py-1 rounded bg-blue-600 text-white text-sm"
+                    } else {
+                        "px-3 py-1 rounded bg-gray-100 dark:bg-gray-800 hover:bg-gray-200 dark:hover:bg-gray-700 text-sm"
+                    },
+                    onclick: apply,
+                    "Apply"
+                }
+            }
+            if let Some(error) = error() {
+                span { class: "w-full text-sm text-red-600", "{error}" }
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::components::{HistoryPoint, Sparkline, Pulsing};
-use crate::hue::client::CompositeSensor;
+use crate::hue::client::{CompositeSensor, SensorHealth};
+use crate::preferences::use_preferences;
 use crate::Route;
 use chrono::Utc;
 use dioxus::prelude::*;
@@ -7,6 +8,8 @@ use std::cmp::Ordering;
 
 #[component]
 pub fn Sensor(sensor: CompositeSensor) -> Element {
+    let prefs = use_preferences()();
+
     // Trend signals
     let mut last_temp = use_signal(|| sensor.temperature.as_ref().map(|t| t.temperature));
     let mut last_light = use_signal(|| sensor.light.as_ref().map(|l| l.light_level));
@@ -78,15 +81,26 @@ pub fn Sensor(sensor: CompositeSensor) -> Element {
         points
     };
 
-    let name_lower = sensor.name.to_lowercase();
-    let (border_class, bg_class, icon) = if sensor.is_outdoor {
-        ("border border-gray-300 dark:border-gray-500", "bg-blue-50 dark:bg-blue-900/10", "🌲")
+    let icon = sensor.icon().to_string();
+    let (border_class, bg_class) = if sensor.is_outdoor {
+        ("border border-gray-300 dark:border-gray-500", "bg-blue-50 dark:bg-blue-900/10")
     } else {
-        let icon = if name_lower.contains("garage") { "🚗" } else if name_lower.contains("shed") { "🛠️" } else { "🏠" };
-        ("border-4 border-gray-300 dark:border-gray-600", "bg-white dark:bg-gray-800", icon)
+        ("border-4 border-gray-300 dark:border-gray-600", "bg-white dark:bg-gray-800")
     };
 
     let disabled_class = if !sensor.enabled { "grayscale opacity-60 contrast-75" } else { "" };
+
+    // Fade cards that have stopped reporting, like the page-level activity indicator
+    let health = if sensor.enabled { sensor.health(current_time) } else { SensorHealth::Fresh };
+    let health_class = match health {
+        SensorHealth::Fresh => "",
+        SensorHealth::Stale => "opacity-80 ring-2 ring-amber-400",
+        SensorHealth::Silent => "opacity-50 grayscale ring-2 ring-red-500",
+    };
+    let last_seen = sensor
+        .last_seen()
+        .map(|t| prefs.format_datetime(t))
+        .unwrap_or_default();
     let motion_class = if let Some(m) = &sensor.motion {
         if !m.enabled { "text-gray-500 italic" } else if m.presence { "text-red-600 dark:text-red-400 font-bold" } else { "text-green-600 dark:text-green-400" }
     } else { "text-gray-400" };
@@ -95,17 +109,35 @@ pub fn Sensor(sensor: CompositeSensor) -> Element {
         Link {
             to: Route::Graphs {
                 sensor_id: sensor.device_id.clone(),
+                range: Default::default(),
             },
             class: "contents",
             Pulsing {
                 key: "{fingerprint}",
                 trigger: fingerprint,
-                class: "p-4 rounded-lg {border_class} {bg_class} {disabled_class} relative overflow-hidden",
+                class: "p-4 rounded-lg {border_class} {bg_class} {disabled_class} {health_class} relative overflow-hidden transition-opacity duration-1000",
                 if !sensor.enabled {
                     div { class: "absolute top-0 right-0 bg-gray-500 text-white text-[10px] font-bold px-2 py-0.5 rounded-bl-md z-20 uppercase tracking-tighter",
                         "Disabled"
                     }
                 }
+                match health {
+                    SensorHealth::Fresh => rsx! {},
+                    SensorHealth::Stale => rsx! {
+                        div {
+                            class: "absolute top-0 right-0 bg-amber-400 text-black text-[10px] font-bold px-2 py-0.5 rounded-bl-md z-20 uppercase tracking-tighter",
+                            title: "Last report {last_seen}",
+                            "Stale"
+                        }
+                    },
+                    SensorHealth::Silent => rsx! {
+                        div {
+                            class: "absolute top-0 right-0 bg-red-500 text-white text-[10px] font-bold px-2 py-0.5 rounded-bl-md z-20 uppercase tracking-tighter",
+                            title: "Last report {last_seen}",
+                            "Silent since {last_seen}"
+                        }
+                    },
+                }
                 div { class: "flex items-center justify-between mb-6",
                     div { class: "bg-gray-50 dark:bg-black/40 px-3 py-1.5 rounded border border-gray-300/50 dark:border-gray-800 shadow-inner flex-grow mr-4 overflow-hidden",
                         h3 { class: "text-base font-bold tracking-wide text-gray-600 dark:text-gray-300 truncate",
@@ -114,10 +146,19 @@ pub fn Sensor(sensor: CompositeSensor) -> Element {
                     }
                     span { class: "text-2xl drop-shadow-sm", "{icon}" }
                 }
+                if !sensor.settings.tags.is_empty() {
+                    div { class: "flex flex-wrap gap-1 -mt-4 mb-3",
+                        for tag in sensor.settings.tags.iter() {
+                            span { class: "text-[10px] px-1.5 py-0.5 rounded bg-gray-200 dark:bg-gray-700 text-gray-600 dark:text-gray-300",
+                                "{tag}"
+                            }
+                        }
+                    }
+                }
                 div { class: "space-y-1",
                     if let Some(m) = &sensor.motion {
                         {
-                            let time = m.last_updated.with_timezone(&chrono::Local).format("%H:%M:%S");
+                            let time = prefs.format_time(m.last_updated);
                             let status = if !m.enabled {
                                 "Disabled"
                             } else if m.presence {
@@ -135,6 +176,7 @@ pub fn Sensor(sensor: CompositeSensor) -> Element {
                                         }
                                         Sparkline {
                                             history: motion_history,
+                                            window: m.history.retention(),
                                             is_discrete: true,
                                             color: "#f87171",
                                             reference_time: current_time,
@@ -146,11 +188,11 @@ pub fn Sensor(sensor: CompositeSensor) -> Element {
                     }
                     if let Some(t) = &sensor.temperature {
                         {
-                            let time = t.last_updated.with_timezone(&chrono::Local).format("%H:%M:%S");
+                            let time = prefs.format_time(t.last_updated);
                             let value_text = if !t.enabled {
                                 "Disabled".to_string()
                             } else {
-                                format!("{:.1}°C", t.temperature)
+                                prefs.format_temperature(t.temperature)
                             };
                             let value_class = if !t.enabled {
                                 "text-gray-500 dark:text-gray-500 italic"
@@ -180,6 +222,7 @@ pub fn Sensor(sensor: CompositeSensor) -> Element {
                                         }
                                         Sparkline {
                                             history: temp_history,
+                                            window: t.history.retention(),
                                             color: "#60a5fa",
                                             reference_time: current_time,
                                         }
@@ -190,11 +233,22 @@ pub fn Sensor(sensor: CompositeSensor) -> Element {
                     }
                     if let Some(l) = &sensor.light {
                         {
-                            let time = l.last_updated.with_timezone(&chrono::Local).format("%H:%M:%S");
+                            let time = prefs.format_time(l.last_updated);
                             let value_text = if !l.enabled {
                                 "Disabled".to_string()
+                            } else if l.lux >= 10.0 {
+                                format!("{:.0} lx", l.lux)
                             } else {
-                                format!("{} lx", l.light_level)
+                                format!("{:.1} lx", l.lux)
+                            };
+                            let daylight_badge = if !l.enabled {
+                                None
+                            } else if l.dark {
+                                Some(("Dark", "bg-indigo-900 text-indigo-100"))
+                            } else if l.daylight {
+                                Some(("Daylight", "bg-yellow-200 text-yellow-900"))
+                            } else {
+                                None
                             };
                             let value_class = if !l.enabled {
                                 "text-gray-500 dark:text-gray-500 italic"
@@ -220,10 +274,14 @@ pub fn Sensor(sensor: CompositeSensor) -> Element {
                                                     }
                                                 }
                                             }
+                                            if let Some((label, badge_class)) = daylight_badge {
+                                                span { class: "text-[10px] font-bold uppercase px-1.5 py-0.5 rounded ml-2 {badge_class}", "{label}" }
+                                            }
                                             span { class: "text-xs text-gray-500 dark:text-gray-500 ml-2", "@{time}" }
                                         }
                                         Sparkline {
                                             history: light_history,
+                                            window: l.history.retention(),
                                             color: "#fbbf24",
                                             reference_time: current_time,
                                         }
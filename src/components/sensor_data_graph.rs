@@ -1,17 +1,62 @@
 use crate::components::HistoryPoint;
+use crate::hue::aggregate::bucket_start;
+use crate::preferences::{use_preferences, Preferences};
 use chrono::{DateTime, Duration, Utc};
 use dioxus::prelude::*;
 
+/// Tick spacings for the time axis, from which the first giving at most `MAX_TICKS` ticks is used
+const TICK_STEPS_MINUTES: [i64; 13] = [
+    5, 10, 15, 30, 60, 180, 360, 720, 1440, 2880, 10080, 20160, 43200,
+];
+const MAX_TICKS: i64 = 8;
+
+/// Time axis labels spaced to suit the range, as the percentage across the axis with the label's
+/// major and minor parts
+pub(crate) fn time_ticks(
+    prefs: &Preferences,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Vec<(f64, String, String)> {
+    let total = (end - start).num_seconds().max(1) as f64;
+    let step_minutes = TICK_STEPS_MINUTES
+        .into_iter()
+        .find(|m| total / (*m as f64 * 60.0) <= MAX_TICKS as f64)
+        .unwrap_or(TICK_STEPS_MINUTES[TICK_STEPS_MINUTES.len() - 1]);
+    let step = Duration::minutes(step_minutes);
+    let mut ticks = Vec::new();
+    let mut tick = bucket_start(start, step);
+    while tick <= end {
+        if tick >= start {
+            let pct = (tick - start).num_seconds() as f64 / total * 100.0;
+            let (major, minor) = if step >= Duration::days(1) {
+                prefs.format_axis_date(tick)
+            } else {
+                prefs.format_axis_time(tick)
+            };
+            ticks.push((pct, major, minor));
+        }
+        tick += step;
+    }
+    ticks
+}
+
+/// Plots `history` across `start..end`. `band`, if given, holds each point's minimum and maximum,
+/// drawn as a shaded area behind the line. Discrete series hold the fraction of time spent on.
 #[component]
 pub fn SensorDataGraph(
     history: Vec<HistoryPoint>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    #[props(default)] band: Vec<(f64, f64)>,
     #[props(default = 1000)] width: u32,
     #[props(default = 300)] height: u32,
     #[props(default = false)] is_discrete: bool,
     #[props(default = String::new())] unit: String,
+    #[props(default = false)] log_scale: bool,
     color: String,
 ) -> Element {
     let mut hovered_point = use_signal(|| None::<HistoryPoint>);
+    let prefs = use_preferences()();
 
     if history.is_empty() {
         return rsx! {
@@ -19,30 +64,30 @@ pub fn SensorDataGraph(
         };
     }
 
-    let end_time = Utc::now();
-    let start_time = end_time - Duration::hours(24);
+    let total = (end - start).num_seconds().max(1) as f64;
 
-    // Normalize X (time) over 24 hours
+    // Normalize X (time) over the range
     let x_scale = move |t: DateTime<Utc>| {
-        let elapsed = (t - start_time).num_seconds() as f64;
-        let total = 24.0 * 3600.0;
+        let elapsed = (t - start).num_seconds() as f64;
         (elapsed / total * width as f64).clamp(0.0, width as f64)
     };
 
-    // Normalize Y (value)
+    // Values are plotted in log10 space when log scale is enabled (clamped to avoid log(0))
+    let to_axis = move |v: f64| if log_scale { v.max(0.01).log10() } else { v };
+    let from_axis = move |v: f64| if log_scale { 10f64.powf(v) } else { v };
+
+    // Normalize Y (value), making room for the band
     let (min_v, max_v) = if is_discrete {
         (-0.1, 1.1)
     } else {
-        let mut min = history[0].value;
-        let mut max = history[0].value;
-        for p in history.iter() {
-            if p.value < min {
-                min = p.value;
-            }
-            if p.value > max {
-                max = p.value;
-            }
-        }
+        let values = history
+            .iter()
+            .map(|p| p.value)
+            .chain(band.iter().flat_map(|(lo, hi)| [*lo, *hi]))
+            .map(to_axis);
+        let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+            (min.min(v), max.max(v))
+        });
         if (max - min).abs() < 0.1 {
             (min - 1.0, max + 1.0)
         } else {
@@ -53,37 +98,32 @@ pub fn SensorDataGraph(
 
     let y_scale = move |v: f64| {
         let range = max_v - min_v;
+        let v = if is_discrete { v } else { to_axis(v) };
         height as f64 - ((v - min_v) / range * height as f64).clamp(0.0, height as f64)
     };
 
-    let mut sorted_history = history.clone();
-    sorted_history.sort_by_key(|p| p.time);
-
-    // Apply smoothing for non-discrete data
-    let display_history = if !is_discrete && sorted_history.len() > 3 {
-        let mut smoothed = Vec::new();
-        let window_size = 5;
-        for i in 0..sorted_history.len() {
-            let start = i.saturating_sub(window_size / 2);
-            let end = (i + window_size / 2).min(sorted_history.len() - 1);
-            let count = (end - start + 1) as f64;
-            let sum: f64 = sorted_history[start..=end].iter().map(|p| p.value).sum();
-            smoothed.push(HistoryPoint {
-                value: sum / count,
-                time: sorted_history[i].time,
-            });
-        }
-        smoothed
+    let display_history = &history;
+
+    let band_path = if band.len() == display_history.len() && !is_discrete {
+        let upper = display_history
+            .iter()
+            .zip(&band)
+            .map(|(p, (_, hi))| format!("{} {}", x_scale(p.time), y_scale(*hi)));
+        let lower = display_history
+            .iter()
+            .zip(&band)
+            .rev()
+            .map(|(p, (lo, _))| format!("{} {}", x_scale(p.time), y_scale(*lo)));
+        let points: Vec<String> = upper.chain(lower).collect();
+        (!points.is_empty()).then(|| format!("M {} Z", points.join(" L ")))
     } else {
-        sorted_history
+        None
     };
 
     let mut path_data = String::new();
-    let mut graph_points = Vec::new();
     for (i, p) in display_history.iter().enumerate() {
         let x = x_scale(p.time);
         let y = y_scale(p.value);
-        graph_points.push((x, y, p.value));
 
         if i == 0 {
             path_data.push_str(&format!("M {} {}", x, y));
@@ -122,25 +162,15 @@ pub fn SensorDataGraph(
         Vec::new()
     };
 
-    // Generate hour labels with percentages for HTML positioning
-    let label_items = (0..=24)
-        .filter(|h| h % 3 == 0)
-        .map(|h| {
-            let time = start_time + Duration::hours(h as i64);
-            let pct = (h as f64 / 24.0) * 100.0;
-            let local_time = time.with_timezone(&chrono::Local);
-            let hours = local_time.format("%H").to_string();
-            let minutes = local_time.format(":%M").to_string();
-            (pct, hours, minutes)
-        })
-        .collect::<Vec<_>>();
+    // Generate time labels with percentages for HTML positioning
+    let label_items = time_ticks(&prefs, start, end);
 
     // Generate Y labels for non-discrete data
     let y_labels = if !is_discrete {
         (0..=4)
             .map(|i| {
                 let pct = (i as f64 / 4.0) * 100.0;
-                let val = max_v - (i as f64 / 4.0) * (max_v - min_v);
+                let val = from_axis(max_v - (i as f64 / 4.0) * (max_v - min_v));
                 (pct, val)
             })
             .collect::<Vec<_>>()
@@ -188,37 +218,25 @@ pub fn SensorDataGraph(
                     }
                 }
 
-                // Path or Points
-                if !is_discrete {
+                if let Some(d) = band_path {
                     path {
-                        d: "{path_data}",
-                        fill: "none",
-                        stroke: "{color}",
-                        stroke_width: "2",
-                        stroke_linejoin: "round",
-                        stroke_linecap: "round",
-                        vector_effect: "non-scaling-stroke"
-                    }
-                } else {
-                    g {
-                        for (x, y, val) in graph_points {
-                            {
-                                let radius = if val > 0.5 { 5 } else { 3 };
-                                let opacity = if val > 0.5 { 1.0 } else { 0.3 };
-                                rsx! {
-                                    circle {
-                                        cx: "{x}",
-                                        cy: "{y}",
-                                        r: "{radius}",
-                                        fill: "{color}",
-                                        fill_opacity: "{opacity}"
-                                    }
-                                }
-                            }
-                        }
+                        d: "{d}",
+                        fill: "{color}",
+                        fill_opacity: "0.2",
+                        stroke: "none",
                     }
                 }
 
+                path {
+                    d: "{path_data}",
+                    fill: "none",
+                    stroke: "{color}",
+                    stroke_width: "2",
+                    stroke_linejoin: "round",
+                    stroke_linecap: "round",
+                    vector_effect: "non-scaling-stroke"
+                }
+
                 // X-Axis ticks (keep in SVG for alignment)
                 for (pct, _, _) in &label_items {
                     {
@@ -311,9 +329,9 @@ pub fn SensorDataGraph(
                 {
                     let x_pct = (x_scale(p.time) / width as f64) * 100.0;
                     let y_pct = (y_scale(p.value) / height as f64) * 100.0;
-                    let time_str = p.time.with_timezone(&chrono::Local).format("%H:%M").to_string();
+                    let time_str = format!("{} {}", prefs.format_date(p.time), prefs.format_short_time(p.time));
                     let val_str = if is_discrete {
-                         if p.value > 0.5 { "Active".to_string() } else { "Inactive".to_string() }
+                         format!("Active {:.0}% of the time", p.value * 100.0)
                     } else {
                          format!("{:.1}{}", p.value, unit)
                     };
@@ -0,0 +1,127 @@
+use crate::hue::client::SensorSettings;
+use crate::hue::{get_sensor_settings, save_sensor_settings};
+use dioxus::prelude::*;
+
+const INPUT_CLASS: &str =
+    "p-1 rounded border border-gray-300 dark:border-gray-600 dark:bg-gray-900";
+
+/// Edits the stored settings (outdoor flag, icon, order, tags, calibration) for one sensor
+#[component]
+pub fn SensorSettingsForm(device_id: String) -> Element {
+    let mut settings = use_signal(SensorSettings::default);
+    let mut status = use_signal(|| None::<Result<(), String>>);
+
+    let id = device_id.clone();
+    use_resource(move || {
+        let id = id.clone();
+        async move {
+            if let Ok(stored) = get_sensor_settings(id).await {
+                settings.set(stored);
+            }
+        }
+    });
+
+    let current = settings.read().clone();
+    let outdoor = match current.is_outdoor {
+        None => "auto",
+        Some(false) => "indoor",
+        Some(true) => "outdoor",
+    };
+    let icon = current.icon.clone().unwrap_or_default();
+    let order = current.display_order.map(|o| o.to_string()).unwrap_or_default();
+    let tags = current.tags.join(", ");
+    let offset = current.temperature_offset;
+
+    rsx! {
+        form {
+            class: "space-y-4",
+            onsubmit: move |e| {
+                e.prevent_default();
+                let id = device_id.clone();
+                async move {
+                    let result = save_sensor_settings(id, settings()).await;
+                    status.set(Some(result.map_err(|e| e.to_string())));
+                }
+            },
+            label { class: "grid grid-cols-[10rem_1fr] items-center gap-4",
+                span { class: "text-gray-600 dark:text-gray-400", "Location" }
+                select {
+                    class: INPUT_CLASS,
+                    value: "{outdoor}",
+                    onchange: move |e| {
+                        settings.write().is_outdoor = match e.value().as_str() {
+                            "indoor" => Some(false),
+                            "outdoor" => Some(true),
+                            _ => None,
+                        };
+                    },
+                    option { value: "auto", selected: outdoor == "auto", "Automatic (from product)" }
+                    option { value: "indoor", selected: outdoor == "indoor", "Indoor" }
+                    option { value: "outdoor", selected: outdoor == "outdoor", "Outdoor" }
+                }
+            }
+            label { class: "grid grid-cols-[10rem_1fr] items-center gap-4",
+                span { class: "text-gray-600 dark:text-gray-400", "Icon" }
+                input {
+                    class: INPUT_CLASS,
+                    placeholder: "Guess from name",
+                    value: "{icon}",
+                    oninput: move |e| {
+                        let value = e.value().trim().to_string();
+                        settings.write().icon = (!value.is_empty()).then_some(value);
+                    },
+                }
+            }
+            label { class: "grid grid-cols-[10rem_1fr] items-center gap-4",
+                span { class: "text-gray-600 dark:text-gray-400", "Display order" }
+                input {
+                    class: INPUT_CLASS,
+                    r#type: "number",
+                    placeholder: "Default",
+                    value: "{order}",
+                    oninput: move |e| settings.write().display_order = e.value().trim().parse().ok(),
+                }
+            }
+            label { class: "grid grid-cols-[10rem_1fr] items-center gap-4",
+                span { class: "text-gray-600 dark:text-gray-400", "Tags" }
+                input {
+                    class: INPUT_CLASS,
+                    placeholder: "Comma separated",
+                    value: "{tags}",
+                    oninput: move |e| {
+                        settings.write().tags = e
+                            .value()
+                            .split(',')
+                            .map(|t| t.trim().to_string())
+                            .filter(|t| !t.is_empty())
+                            .collect();
+                    },
+                }
+            }
+            label { class: "grid grid-cols-[10rem_1fr] items-center gap-4",
+                span { class: "text-gray-600 dark:text-gray-400", "Temperature offset (°C)" }
+                input {
+                    class: INPUT_CLASS,
+                    r#type: "number",
+                    step: "0.1",
+                    value: "{offset}",
+                    oninput: move |e| {
+                        settings.write().temperature_offset = e.value().trim().parse().unwrap_or(0.0);
+                    },
+                }
+            }
+            div { class: "flex items-center gap-4 pt-2 border-t border-gray-200 dark:border-gray-700",
+                button {
+                    r#type: "submit",
+                    class: "px-3 py-1 rounded bg-blue-600 text-white hover:bg-blue-700",
+                    "Save"
+                }
+                match status() {
+                    Some(Ok(())) => rsx! { span { class: "text-sm text-green-600", "Saved" } },
+                    Some(Err(e)) => rsx! { span { class: "text-sm text-red-600", "{e}" } },
+                    None => rsx! {},
+                }
+            }
+        }
+    }
+}
@@ -15,6 +15,9 @@ pub fn Sparkline(
     #[props(default = false)] is_discrete: bool,
     color: String,
     reference_time: DateTime<Utc>,
+    /// How much history the sparkline spans, ending at `reference_time`
+    #[props(default = Duration::minutes(15))]
+    window: Duration,
 ) -> Element {
     let points = history;
     if points.len() < 2 {
@@ -26,12 +29,12 @@ pub fn Sparkline(
     }
 
     let now = reference_time;
-    let ten_mins_ago = now - Duration::minutes(10);
+    let window_start = now - window;
 
     // Normalize X (time)
     let x_scale = |t: DateTime<Utc>| {
-        let elapsed = (t - ten_mins_ago).num_seconds() as f64;
-        let total = 600.0; // 10 minutes
+        let elapsed = (t - window_start).num_seconds() as f64;
+        let total = window.num_seconds().max(1) as f64;
         (elapsed / total * width as f64).clamp(0.0, width as f64)
     };
 
@@ -0,0 +1,228 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Summary of the readings within one time bucket
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Aggregate {
+    pub start: DateTime<Utc>,
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+}
+
+/// The span of history shown on a graph
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum GraphRange {
+    Hour,
+    #[default]
+    Day,
+    Week,
+    Month,
+    Year,
+    Custom {
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    },
+}
+
+impl GraphRange {
+    pub const PRESETS: [GraphRange; 5] = [
+        GraphRange::Hour,
+        GraphRange::Day,
+        GraphRange::Week,
+        GraphRange::Month,
+        GraphRange::Year,
+    ];
+
+    /// The start and end of the range, with presets ending at `now`
+    pub fn bounds(&self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let length = match self {
+            Self::Hour => Duration::hours(1),
+            Self::Day => Duration::hours(24),
+            Self::Week => Duration::days(7),
+            Self::Month => Duration::days(30),
+            Self::Year => Duration::days(365),
+            Self::Custom { start, end } => return (*start, *end),
+        };
+        (now - length, now)
+    }
+}
+
+/// Ranges appear in the URL as `1h`, `24h`, `7d`, `30d`, `1y`, or `<start>..<end>` in Unix seconds
+impl fmt::Display for GraphRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Hour => write!(f, "1h"),
+            Self::Day => write!(f, "24h"),
+            Self::Week => write!(f, "7d"),
+            Self::Month => write!(f, "30d"),
+            Self::Year => write!(f, "1y"),
+            Self::Custom { start, end } => write!(f, "{}..{}", start.timestamp(), end.timestamp()),
+        }
+    }
+}
+
+impl FromStr for GraphRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1h" => Ok(Self::Hour),
+            "" | "24h" => Ok(Self::Day),
+            "7d" => Ok(Self::Week),
+            "30d" => Ok(Self::Month),
+            "1y" => Ok(Self::Year),
+            custom => {
+                let parse = |secs: &str| {
+                    secs.parse::<i64>()
+                        .ok()
+                        .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+                };
+                let (start, end) = custom
+                    .split_once("..")
+                    .and_then(|(start, end)| Some((parse(start)?, parse(end)?)))
+                    .filter(|(start, end)| start < end)
+                    .ok_or_else(|| format!("Invalid range: {}", s))?;
+                Ok(Self::Custom { start, end })
+            }
+        }
+    }
+}
+
+/// The bucket length that splits `start..end` into about `buckets` buckets, in whole seconds
+pub fn bucket_size(start: DateTime<Utc>, end: DateTime<Utc>, buckets: usize) -> Duration {
+    let secs = (end - start).num_seconds().max(1);
+    Duration::seconds((secs + buckets.max(1) as i64 - 1) / buckets.max(1) as i64)
+}
+
+/// The start of the bucket containing `t`. Buckets are aligned to the Unix epoch, so the same
+/// bucket length always yields the same boundaries.
+pub fn bucket_start(t: DateTime<Utc>, bucket: Duration) -> DateTime<Utc> {
+    let size = bucket.num_milliseconds().max(1);
+    let ms = t.timestamp_millis();
+    DateTime::from_timestamp_millis(ms - ms.rem_euclid(size)).unwrap_or(t)
+}
+
+/// Groups readings within `start..=end` into buckets
+pub fn aggregate(
+    series: &[(DateTime<Utc>, f64)],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: Duration,
+) -> Vec<Aggregate> {
+    let mut buckets: Vec<(Aggregate, usize)> = Vec::new();
+    for (t, v) in series.iter().filter(|(t, _)| *t >= start && *t <= end) {
+        let b = bucket_start(*t, bucket);
+        match buckets.last_mut() {
+            Some((agg, count)) if agg.start == b => {
+                agg.min = agg.min.min(*v);
+                agg.max = agg.max.max(*v);
+                agg.avg += v;
+                *count += 1;
+            }
+            _ => buckets.push((
+                Aggregate {
+                    start: b,
+                    min: *v,
+                    avg: *v,
+                    max: *v,
+                },
+                1,
+            )),
+        }
+    }
+    buckets
+        .into_iter()
+        .map(|(agg, count)| Aggregate {
+            avg: agg.avg / count as f64,
+            ..agg
+        })
+        .collect()
+}
+
+/// Where a read switches from rollups to raw readings: the start of the bucket holding the rollups'
+/// watermark, kept within `start..=end`. Each bucket is then read whole from one source, since
+/// averages from the two can't be combined without knowing how much each covers.
+pub fn rollup_split(
+    watermark: Option<DateTime<Utc>>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: Duration,
+) -> DateTime<Utc> {
+    watermark.map_or(start, |w| bucket_start(w, bucket).clamp(start, end))
+}
+
+/// Fills the empty buckets between `aggregates` and up to `end`, each holding the value of the last
+/// bucket before it, since sensors that report on change (such as light level) hold their value
+/// until the next reading. The value carried is the bucket's average. Buckets starting more than
+/// `max_gap` after the last one with readings stay empty, as the sensor has stopped reporting.
+pub fn carry_forward(
+    aggregates: &[Aggregate],
+    end: DateTime<Utc>,
+    bucket: Duration,
+    max_gap: Duration,
+) -> Vec<Aggregate> {
+    if bucket <= Duration::zero() {
+        return aggregates.to_vec();
+    }
+    let mut filled = Vec::with_capacity(aggregates.len());
+    for (i, a) in aggregates.iter().enumerate() {
+        filled.push(*a);
+        let until = aggregates.get(i + 1).map_or(end, |next| next.start);
+        let mut start = a.start + bucket;
+        while start < until && start - a.start <= max_gap {
+            filled.push(Aggregate {
+                start,
+                min: a.avg,
+                avg: a.avg,
+                max: a.avg,
+            });
+            start += bucket;
+        }
+    }
+    filled
+}
+
+/// The fraction of each bucket in `start..end` that an on/off series (such as motion) spent on.
+/// Each reading holds until the next, so a reading before `start` sets the initial state.
+pub fn duty_cycle(
+    series: &[(DateTime<Utc>, f64)],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: Duration,
+) -> Vec<(DateTime<Utc>, f64)> {
+    if start >= end {
+        return Vec::new();
+    }
+    let size = bucket.num_milliseconds().max(1);
+    let first = bucket_start(start, bucket);
+    let count = ((end - first).num_milliseconds() / size + 1) as usize;
+    let bucket_at = |i: usize| first + Duration::milliseconds(size * i as i64);
+
+    let mut on_ms = vec![0i64; count];
+    for (i, (t, v)) in series.iter().enumerate() {
+        if *v == 0.0 {
+            continue;
+        }
+        let mut from = (*t).max(start);
+        let to = series.get(i + 1).map_or(end, |(next, _)| *next).min(end);
+        while from < to {
+            let idx = ((from - first).num_milliseconds() / size) as usize;
+            let segment_end = bucket_at(idx + 1).min(to);
+            on_ms[idx] += (segment_end - from).num_milliseconds();
+            from = segment_end;
+        }
+    }
+
+    on_ms
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, on)| {
+            // The first and last buckets may only partly overlap the range
+            let span = (bucket_at(i + 1).min(end) - bucket_at(i).max(start)).num_milliseconds();
+            (span > 0).then(|| (bucket_at(i), on as f64 / span as f64))
+        })
+        .collect()
+}
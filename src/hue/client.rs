@@ -10,6 +10,8 @@ use tokio::sync::Semaphore;
 
 #[cfg(feature = "server")]
 use crate::hue::client::types::ErrorResponse;
+#[cfg(feature = "server")]
+use crate::hue::history::{History, HistoryRetention};
 
 // Generate Hue OpenAPI bindings
 // NB re-evaluated when the openapi spec file changes
@@ -106,15 +108,21 @@ impl ClientEx {
         }
     }
 
-    /// Fetch all sensors and group them by device into CompositeSensors
-    pub async fn get_sensors(&self) -> Result<Vec<CompositeSensor>, Error<ErrorResponse>> {
-        let (motion_res, temp_res, light_res, devices_res) = self
+    /// Fetch all sensors and group them by device into CompositeSensors, applying each device's
+    /// stored settings
+    pub async fn get_sensors(
+        &self,
+        settings: &HashMap<String, SensorSettings>,
+    ) -> Result<Vec<CompositeSensor>, Error<ErrorResponse>> {
+        let (motion_res, temp_res, light_res, devices_res, rooms_res, zones_res) = self
             .retry(|| async {
                 tokio::try_join!(
                     self.inner.get_motion_sensors(),
                     self.inner.get_temperatures(),
                     self.inner.get_light_levels(),
-                    self.inner.get_devices()
+                    self.inner.get_devices(),
+                    self.inner.get_rooms(),
+                    self.inner.get_zones()
                 )
             })
             .await?;
@@ -124,7 +132,8 @@ impl ClientEx {
         let light_response = light_res;
         let devices_response = devices_res;
 
-        let mut device_map = self.init_device_map(&devices_response.data);
+        let mut device_map = self.init_device_map(&devices_response.data, settings);
+        let retention = HistoryRetention::current();
 
         // Populate motion data
         for m in &motion_response.data {
@@ -139,7 +148,11 @@ impl ClientEx {
                             enabled: m.enabled.unwrap_or(true),
                             presence,
                             last_updated,
-                            history: Arc::new(vec![(last_updated, presence)]),
+                            history: Arc::new(History::with_sample(
+                                retention.motion,
+                                last_updated,
+                                presence,
+                            )),
                         });
                     }
                 }
@@ -155,7 +168,8 @@ impl ClientEx {
                         .as_ref()
                         .and_then(|t| t.temperature_report.as_ref())
                     {
-                        let temperature = report.temperature.unwrap_or(0.0);
+                        let temperature =
+                            report.temperature.unwrap_or(0.0) + cs.settings.temperature_offset;
                         let last_updated = report.changed.unwrap_or_else(Utc::now);
                         cs.temperature = Some(TemperatureData {
                             id: id.to_string(),
@@ -163,7 +177,11 @@ impl ClientEx {
                             enabled: t.enabled.unwrap_or(true),
                             temperature,
                             last_updated,
-                            history: Arc::new(vec![(last_updated, temperature)]),
+                            history: Arc::new(History::with_sample(
+                                retention.temperature,
+                                last_updated,
+                                temperature,
+                            )),
                         });
                     }
                 }
@@ -179,14 +197,13 @@ impl ClientEx {
                     {
                         let light_level = report.light_level.map(|v| v as i32).unwrap_or(0);
                         let last_updated = report.changed.unwrap_or_else(Utc::now);
-                        cs.light = Some(LightData {
-                            id: id.to_string(),
-                            id_v1: l.id_v1.as_ref().map(|v| v.to_string()),
-                            enabled: l.enabled.unwrap_or(true),
+                        cs.light = Some(LightData::new(
+                            id.to_string(),
+                            l.id_v1.as_ref().map(|v| v.to_string()),
+                            l.enabled.unwrap_or(true),
                             light_level,
                             last_updated,
-                            history: Arc::new(vec![(last_updated, light_level)]),
-                        });
+                        ));
                     }
                 }
             }
@@ -198,16 +215,51 @@ impl ClientEx {
                 && cs.light.as_ref().map(|l| l.enabled).unwrap_or(true);
         }
 
+        // Place sensors in their room and zones
+        let rooms: Vec<_> = rooms_res
+            .data
+            .iter()
+            .filter_map(|room| {
+                Self::group_with_children(
+                    room.id.as_ref().map(|id| id.to_string()),
+                    room.metadata.as_ref().and_then(|m| m.name.as_ref()).map(|n| n.to_string()),
+                    &room.children,
+                )
+            })
+            .collect();
+        let zones: Vec<_> = zones_res
+            .data
+            .iter()
+            .filter_map(|zone| {
+                Self::group_with_children(
+                    zone.id.as_ref().map(|id| id.to_string()),
+                    zone.metadata.as_ref().and_then(|m| m.name.as_ref()).map(|n| n.to_string()),
+                    &zone.children,
+                )
+            })
+            .collect();
+
+        for cs in device_map.values_mut() {
+            cs.room = rooms
+                .iter()
+                .find(|(_, children)| children.contains(&cs.device_id))
+                .map(|(room, _)| room.clone());
+
+            let room_id = cs.room.as_ref().map(|r| &r.id);
+            let ids: Vec<&String> = cs.resource_ids().chain(room_id).collect();
+            cs.zones = zones
+                .iter()
+                .filter(|(_, children)| children.iter().any(|rid| ids.contains(&rid)))
+                .map(|(zone, _)| zone.clone())
+                .collect();
+        }
+
         let mut sensors: Vec<CompositeSensor> = device_map
             .into_values()
             .filter(|cs| cs.motion.is_some() || cs.temperature.is_some() || cs.light.is_some())
             .collect();
 
-        sensors.sort_by(|a, b| {
-            b.is_outdoor
-                .cmp(&a.is_outdoor)
-                .then_with(|| a.name.cmp(&b.name))
-        });
+        sensors.sort_by(|a, b| a.display_cmp(b));
 
         Ok(sensors)
     }
@@ -332,6 +384,7 @@ impl ClientEx {
     fn init_device_map(
         &self,
         devices: &[crate::hue::client::types::GetDevicesResponseDataItem],
+        settings: &HashMap<String, SensorSettings>,
     ) -> HashMap<String, CompositeSensor> {
         devices
             .iter()
@@ -344,22 +397,38 @@ impl ClientEx {
                     .and_then(|pd| pd.product_name.as_ref())
                     .map(|pn| pn.to_lowercase().contains("outdoor"))
                     .unwrap_or(false);
+                let settings = settings.get(&id).cloned().unwrap_or_default();
                 Some((
                     id.clone(),
                     CompositeSensor {
                         device_id: id,
                         name,
-                        is_outdoor,
+                        is_outdoor: settings.is_outdoor.unwrap_or(is_outdoor),
                         enabled: true,
                         motion: None,
                         temperature: None,
                         light: None,
+                        room: None,
+                        zones: Vec::new(),
+                        settings,
                     },
                 ))
             })
             .collect()
     }
 
+    fn group_with_children(
+        id: Option<String>,
+        name: Option<String>,
+        children: &[crate::hue::client::types::ResourceIdentifier],
+    ) -> Option<(SensorGroup, Vec<String>)> {
+        let children = children
+            .iter()
+            .filter_map(|c| c.rid.as_ref().map(|rid| rid.to_string()))
+            .collect();
+        Some((SensorGroup { id: id?, name: name? }, children))
+    }
+
     fn insert_resource_names(
         map: &mut HashMap<String, String>,
         id: Option<String>,
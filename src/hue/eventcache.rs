@@ -1,50 +1,319 @@
 #[cfg(feature = "server")]
+use crate::hue::events::{CachedEvent, EventCursor, EventQuery, HueEvent};
+#[cfg(feature = "server")]
 use chrono::{DateTime, Duration, Utc};
 #[cfg(feature = "server")]
 use std::collections::VecDeque;
 #[cfg(feature = "server")]
-use std::sync::RwLock;
+use std::fs::{File, OpenOptions};
+#[cfg(feature = "server")]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(feature = "server")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "server")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "server")]
+use std::sync::{Mutex, RwLock};
+
+/// Minimum number of appended lines before the log is considered for compaction
+#[cfg(feature = "server")]
+const MIN_COMPACTION_LINES: usize = 1000;
+
+#[cfg(feature = "server")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LogEntry {
+    #[serde(default)]
+    seq: u64,
+    ts: DateTime<Utc>,
+    event: String,
+}
+
+#[cfg(feature = "server")]
+impl LogEntry {
+    fn line(cached: &CachedEvent) -> std::io::Result<String> {
+        let mut line = serde_json::to_string(&LogEntry {
+            seq: cached.seq,
+            ts: cached.received,
+            event: cached.raw.clone(),
+        })?;
+        line.push('\n');
+        Ok(line)
+    }
+}
+
+/// Append-only JSON lines file backing the cache.
+///
+/// Each event is written as a single line, so a crash can at worst leave a truncated last line,
+/// which is skipped on reload. The file is periodically rewritten (via a temp file and an atomic
+/// rename) with only the unexpired events, which keeps it bounded to roughly twice the cache size.
+#[cfg(feature = "server")]
+struct EventLog {
+    path: PathBuf,
+    file: File,
+    lines: usize,
+}
+
+#[cfg(feature = "server")]
+impl EventLog {
+    fn open_append(path: &Path) -> std::io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    /// Reloads unexpired events. They join the numbering of the run reloading them.
+    fn load(path: &Path, cutoff: DateTime<Utc>, epoch: u64) -> VecDeque<CachedEvent> {
+        let Ok(file) = File::open(path) else {
+            return VecDeque::new();
+        };
+        BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<LogEntry>(&line).ok())
+            .filter(|entry| entry.ts >= cutoff)
+            .filter_map(|entry| {
+                let v = serde_json::from_str::<serde_json::Value>(&entry.event).ok()?;
+                Some(CachedEvent {
+                    seq: entry.seq,
+                    epoch,
+                    received: entry.ts,
+                    event: HueEvent::from_json(&v)?,
+                    raw: entry.event,
+                })
+            })
+            .collect()
+    }
+
+    fn append(&mut self, cached: &CachedEvent) -> std::io::Result<()> {
+        self.file.write_all(LogEntry::line(cached)?.as_bytes())?;
+        self.lines += 1;
+        Ok(())
+    }
+
+    fn compact(&mut self, events: &VecDeque<CachedEvent>) -> std::io::Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            for cached in events {
+                tmp.write_all(LogEntry::line(cached)?.as_bytes())?;
+            }
+            tmp.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, &self.path)?;
+        self.file = Self::open_append(&self.path)?;
+        self.lines = events.len();
+        Ok(())
+    }
+}
+
+/// A new epoch for each cache, distinct even for caches created within the same microsecond
+#[cfg(feature = "server")]
+fn new_epoch() -> u64 {
+    static LAST: AtomicU64 = AtomicU64::new(0);
+    let now = Utc::now().timestamp_micros().max(0) as u64;
+    let last = LAST
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some(now.max(last + 1)))
+        .unwrap_or(0);
+    now.max(last + 1)
+}
+
+/// Cached events to replay to a (re)connecting client
+#[cfg(feature = "server")]
+pub struct Replay {
+    pub events: Vec<CachedEvent>,
+    /// The newest position assigned when the replay was taken
+    pub latest: EventCursor,
+    /// The client's cursor could not be honoured
+    pub resync_required: bool,
+}
+
+#[cfg(feature = "server")]
+struct Inner {
+    events: VecDeque<CachedEvent>,
+    next_seq: u64,
+}
 
 #[cfg(feature = "server")]
 pub struct EventCache {
-    events: RwLock<VecDeque<(DateTime<Utc>, String)>>,
+    inner: RwLock<Inner>,
+    /// Identifies this run of the server. Cursors from another run are refused, since events
+    /// while the server was down were never seen and numbering may have started again.
+    epoch: u64,
     max_age: Duration,
+    log: Option<Mutex<EventLog>>,
 }
 
 #[cfg(feature = "server")]
 impl EventCache {
     pub fn new(max_age_minutes: i64) -> Self {
         Self {
-            events: RwLock::new(VecDeque::new()),
+            inner: RwLock::new(Inner {
+                events: VecDeque::new(),
+                next_seq: 1,
+            }),
+            epoch: new_epoch(),
             max_age: Duration::minutes(max_age_minutes),
+            log: None,
         }
     }
 
-    pub fn add(&self, event: String) {
+    /// Creates a cache backed by an append-only log at `path`. Unexpired events from a previous run
+    /// are reloaded and the log is compacted. Falls back to a memory-only cache if the file can't be used.
+    pub fn with_log(max_age_minutes: i64, path: impl Into<PathBuf>) -> Self {
+        let mut cache = Self::new(max_age_minutes);
+        let path = path.into();
+
+        let events = EventLog::load(&path, Utc::now() - cache.max_age, cache.epoch);
+        let log = EventLog::open_append(&path).and_then(|file| {
+            let mut log = EventLog {
+                path: path.clone(),
+                file,
+                lines: 0,
+            };
+            log.compact(&events)?;
+            Ok(log)
+        });
+
+        match log {
+            Ok(log) => {
+                println!(
+                    "Reloaded {} cached events from {}",
+                    events.len(),
+                    path.display()
+                );
+                // Continue numbering after the reloaded events so sequence numbers stay monotonic
+                let next_seq = events.iter().map(|e| e.seq).max().unwrap_or(0) + 1;
+                cache.inner = RwLock::new(Inner { events, next_seq });
+                cache.log = Some(Mutex::new(log));
+            }
+            Err(e) => {
+                println!(
+                    "Unable to use event cache file {}: {}. Caching in memory only.",
+                    path.display(),
+                    e
+                );
+            }
+        }
+        cache
+    }
+
+    /// Adds a parsed event along with its original JSON, returning the cached entry
+    pub fn add(&self, event: HueEvent, raw: String) -> CachedEvent {
         let now = Utc::now();
-        let mut events = self.events.write().unwrap();
-        events.push_back((now, event));
+        let mut inner = self.inner.write().unwrap();
+
+        let cached = CachedEvent {
+            seq: inner.next_seq,
+            epoch: self.epoch,
+            received: now,
+            event,
+            raw,
+        };
+        inner.next_seq += 1;
+
+        if let Some(log) = &self.log {
+            let mut log = log.lock().unwrap();
+            if let Err(e) = log.append(&cached) {
+                println!("Error writing event cache file: {}", e);
+            }
+        }
+
+        inner.events.push_back(cached.clone());
 
         // Prune old events while we have the write lock
         let cutoff = now - self.max_age;
-        while let Some((ts, _)) = events.front() {
-            if *ts < cutoff {
-                events.pop_front();
+        while let Some(front) = inner.events.front() {
+            if front.received < cutoff {
+                inner.events.pop_front();
             } else {
                 break;
             }
         }
+
+        if let Some(log) = &self.log {
+            let mut log = log.lock().unwrap();
+            if log.lines > MIN_COMPACTION_LINES && log.lines > inner.events.len() * 2 {
+                if let Err(e) = log.compact(&inner.events) {
+                    println!("Error compacting event cache file: {}", e);
+                }
+            }
+        }
+
+        cached
     }
 
-    pub fn get_all(&self) -> Vec<String> {
-        let events = self.events.read().unwrap();
-        let now = Utc::now();
-        let cutoff = now - self.max_age;
+    /// Returns the events a client needs to catch up. With a cursor, exactly the events after it are
+    /// returned, or a resync is flagged if it is from another run or some of them have already
+    /// expired. Without a cursor, the whole cache is returned if `include_cached` is set.
+    pub fn replay(&self, since: Option<EventCursor>, include_cached: bool) -> Replay {
+        let inner = self.inner.read().unwrap();
+        let latest = EventCursor {
+            epoch: self.epoch,
+            seq: inner.next_seq - 1,
+        };
 
-        events
-            .iter()
-            .filter(|(ts, _)| *ts >= cutoff)
-            .map(|(_, msg)| msg.clone())
-            .collect()
+        match since {
+            Some(cursor) => {
+                let oldest = inner
+                    .events
+                    .front()
+                    .map(|e| e.seq)
+                    .unwrap_or(inner.next_seq);
+                // A cursor from another run can't be compared with ours; one before the oldest
+                // cached event means the events in between have been pruned
+                if cursor.epoch != self.epoch || cursor.seq > latest.seq || cursor.seq + 1 < oldest {
+                    return Replay {
+                        events: Vec::new(),
+                        latest,
+                        resync_required: true,
+                    };
+                }
+                let start = inner.events.partition_point(|e| e.seq <= cursor.seq);
+                Replay {
+                    events: inner.events.range(start..).cloned().collect(),
+                    latest,
+                    resync_required: false,
+                }
+            }
+            None => Replay {
+                events: if include_cached {
+                    let cutoff = Utc::now() - self.max_age;
+                    inner
+                        .events
+                        .iter()
+                        .filter(|e| e.received >= cutoff)
+                        .cloned()
+                        .collect()
+                } else {
+                    Vec::new()
+                },
+                latest,
+                resync_required: false,
+            },
+        }
+    }
+
+    pub fn get_all(&self) -> Vec<CachedEvent> {
+        self.query(&EventQuery::default())
+    }
+
+    /// Returns unexpired events matching the query, oldest first
+    pub fn query(&self, query: &EventQuery) -> Vec<CachedEvent> {
+        let inner = self.inner.read().unwrap();
+        let cutoff = Utc::now() - self.max_age;
+        let since = query.since.map_or(cutoff, |since| since.max(cutoff));
+
+        // Events are in arrival order, so the time range can be located by binary search
+        let start = inner.events.partition_point(|e| e.received < since);
+        let mut matches: Vec<CachedEvent> = inner
+            .events
+            .range(start..)
+            .filter(|e| query.matches(e))
+            .cloned()
+            .collect();
+
+        if let Some(limit) = query.limit {
+            let excess = matches.len().saturating_sub(limit);
+            matches.drain(..excess);
+        }
+        matches
     }
 }
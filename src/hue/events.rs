@@ -110,6 +110,16 @@ impl HueEvent {
         }
     }
 
+    /// The bridge resource type, e.g. `motion`, `temperature` or `light_level`
+    pub fn event_type(&self) -> Option<&str> {
+        match self {
+            Self::Motion { .. } => Some("motion"),
+            Self::Temperature { .. } => Some("temperature"),
+            Self::LightLevel { .. } => Some("light_level"),
+            Self::Raw(v) => v.get("type").and_then(|t| t.as_str()),
+        }
+    }
+
     pub fn resource_id(&self) -> Option<&str> {
         match self {
             Self::Motion { id, .. } => Some(id),
@@ -120,7 +130,210 @@ impl HueEvent {
     }
 }
 
+/// An event held in the server's event cache
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CachedEvent {
+    /// Monotonically increasing sequence number assigned on arrival
+    pub seq: u64,
+    /// The server run that assigned `seq`
+    #[serde(default)]
+    pub epoch: u64,
+    /// When the server received the event
+    pub received: DateTime<Utc>,
+    pub event: HueEvent,
+    /// The original bridge JSON, kept for replaying to raw event streams
+    #[serde(skip)]
+    pub raw: String,
+}
+
+impl CachedEvent {
+    /// The position just after this event
+    pub fn cursor(&self) -> EventCursor {
+        EventCursor {
+            epoch: self.epoch,
+            seq: self.seq,
+        }
+    }
+}
+
+/// A position in the event stream to resume from. Sequence numbers are only comparable within one
+/// server run, so a cursor from an earlier run (whose numbering may have started again) is refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct EventCursor {
+    pub epoch: u64,
+    pub seq: u64,
+}
+
+/// Filter for querying the event cache. Unset fields match everything.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EventQuery {
+    pub resource_id: Option<String>,
+    pub owner_rid: Option<String>,
+    pub event_type: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Return at most this many of the most recent matches
+    pub limit: Option<usize>,
+}
+
+impl EventQuery {
+    pub fn matches(&self, cached: &CachedEvent) -> bool {
+        let event = &cached.event;
+        self.resource_id
+            .as_deref()
+            .is_none_or(|id| event.resource_id() == Some(id))
+            && self
+                .owner_rid
+                .as_deref()
+                .is_none_or(|rid| event.owner_rid() == Some(rid))
+            && self
+                .event_type
+                .as_deref()
+                .is_none_or(|t| event.event_type() == Some(t))
+            && self.since.is_none_or(|since| cached.received >= since)
+            && self.until.is_none_or(|until| cached.received < until)
+    }
+}
+
+/// Restricts which events a `hue_events` subscriber receives. Empty lists match everything; the
+/// device and room lists together select the devices whose events are sent.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EventFilter {
+    /// Resource types, e.g. `motion`, `temperature`, `light_level`, `button`
+    #[serde(default)]
+    pub resource_types: Vec<String>,
+    #[serde(default)]
+    pub device_ids: Vec<String>,
+    /// Room or zone ids
+    #[serde(default)]
+    pub rooms: Vec<String>,
+}
+
+impl EventFilter {
+    pub fn resource_types(types: &[&str]) -> Self {
+        Self {
+            resource_types: types.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Combines filters into one that passes every event any of them would
+    pub fn union<'a>(filters: impl IntoIterator<Item = &'a EventFilter>) -> Self {
+        let mut combined = Self::default();
+        let mut all_types = false;
+        let mut all_members = false;
+
+        for filter in filters {
+            if filter.resource_types.is_empty() {
+                all_types = true;
+            }
+            if filter.device_ids.is_empty() && filter.rooms.is_empty() {
+                all_members = true;
+            }
+            for (into, from) in [
+                (&mut combined.resource_types, &filter.resource_types),
+                (&mut combined.device_ids, &filter.device_ids),
+                (&mut combined.rooms, &filter.rooms),
+            ] {
+                for item in from {
+                    if !into.contains(item) {
+                        into.push(item.clone());
+                    }
+                }
+            }
+        }
+
+        if all_types {
+            combined.resource_types.clear();
+        }
+        if all_members {
+            combined.device_ids.clear();
+            combined.rooms.clear();
+        }
+        combined
+    }
 
+    /// Resolves `device_ids` and `rooms` into the resource ids they select, or `None` when neither
+    /// is set. Rooms are resolved from the room and zones each sensor carries, so only sensors in
+    /// `sensors` are found in them.
+    pub fn members(
+        &self,
+        sensors: &[crate::hue::models::CompositeSensor],
+    ) -> Option<std::collections::HashSet<String>> {
+        if self.device_ids.is_empty() && self.rooms.is_empty() {
+            return None;
+        }
+        let mut members: std::collections::HashSet<String> = self.device_ids.iter().cloned().collect();
+        for sensor in sensors {
+            let in_room = sensor
+                .room
+                .iter()
+                .chain(&sensor.zones)
+                .any(|group| self.rooms.contains(&group.id));
+            if in_room {
+                members.extend(sensor.resource_ids().cloned());
+            }
+        }
+        Some(members)
+    }
 
+    /// Checks an event against the filter. `members` holds the resource ids selected by `device_ids`
+    /// and `rooms` (see [`Self::members`]), or `None` when neither is set.
+    pub fn matches(
+        &self,
+        event: &HueEvent,
+        members: Option<&std::collections::HashSet<String>>,
+    ) -> bool {
+        let type_matches = self.resource_types.is_empty()
+            || event
+                .event_type()
+                .is_some_and(|t| self.resource_types.iter().any(|rt| rt == t));
+        let member_matches = members.is_none_or(|members| {
+            event.owner_rid().is_some_and(|rid| members.contains(rid))
+                || event.resource_id().is_some_and(|id| members.contains(id))
+        });
+        type_matches && member_matches
+    }
+}
+
+/// A message on the `hue_events` stream. Messages are sent as newline-delimited JSON.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StreamMessage {
+    /// A bridge event, carrying its sequence number for resumption
+    Event(CachedEvent),
+    /// The position the client is up to date with, sent after any replayed events
+    Cursor(EventCursor),
+    /// The client fell behind the live stream and `skipped` events were dropped; the client should
+    /// refetch its full state
+    Lagged { skipped: u64 },
+    /// The requested cursor can't be resumed from (its events have expired, or it is from before
+    /// the server restarted); the client should refetch its full state
+    ResyncRequired,
+}
+
+impl StreamMessage {
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_string(self).unwrap_or_default();
+        line.push('\n');
+        line
+    }
+}
 
+/// A message on the `sensor_updates` stream. Messages are sent as newline-delimited JSON.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SensorStreamMessage {
+    Update(crate::hue::models::SensorUpdate),
+    /// Updates may have been missed (on connect, or after lagging) or the sensors themselves changed,
+    /// so the client should refetch the full sensor list
+    Resync,
+}
 
+impl SensorStreamMessage {
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_string(self).unwrap_or_default();
+        line.push('\n');
+        line
+    }
+}
@@ -0,0 +1,289 @@
+use crate::hue::aggregate::GraphRange;
+use crate::hue::models::Metric;
+use crate::preferences::TemperatureUnit;
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv",
+            Self::Json => "application/json",
+        }
+    }
+}
+
+/// Where exports are downloaded from, with the [`ExportRequest`] in the query
+pub const EXPORT_PATH: &str = "/api/export";
+
+/// An export of the history of `sensor_ids` over `range` in buckets of `bucket_minutes`, with
+/// times in `time_zone` and temperatures in `temperature_unit`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportRequest {
+    pub sensor_ids: Vec<String>,
+    pub range: GraphRange,
+    pub bucket_minutes: u32,
+    pub format: ExportFormat,
+    pub time_zone: ExportTimeZone,
+    pub temperature_unit: TemperatureUnit,
+}
+
+impl ExportRequest {
+    /// The URL the browser downloads the export from, so it is written to disk as it arrives
+    pub fn url(&self) -> String {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        format!("{}?request={}", EXPORT_PATH, percent_encode(&json))
+    }
+
+    /// Reads a request from the query of its [`Self::url`]
+    pub fn from_query(query: &str) -> Result<Self, String> {
+        let value = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("request="))
+            .ok_or("Missing export request")?;
+        let json = percent_decode(value).ok_or("Malformed export request")?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid export request: {}", e))
+    }
+
+    pub fn file_name(&self, now: DateTime<Utc>) -> String {
+        let (start, end) = self.range.bounds(now);
+        format!(
+            "huebot-history-{}-{}.{}",
+            start.format("%Y%m%d"),
+            end.format("%Y%m%d"),
+            self.format.extension()
+        )
+    }
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+                out.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b'+' => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// The zone exported timestamps are written in
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ExportTimeZone {
+    /// An IANA time zone name
+    Named(String),
+    /// A fixed offset east of UTC in seconds, for browsers whose zone has no name in the preferences
+    Offset(i32),
+}
+
+impl ExportTimeZone {
+    /// Fails for a zone name chrono-tz doesn't know or an offset of a day or more, which would
+    /// otherwise be treated as UTC
+    pub fn validate(&self) -> Result<(), String> {
+        let valid = match self {
+            Self::Named(name) => name.parse::<Tz>().is_ok(),
+            Self::Offset(secs) => FixedOffset::east_opt(*secs).is_some(),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("Unknown time zone {:?}", self))
+        }
+    }
+
+    pub fn localize(&self, t: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            Self::Named(name) => match name.parse::<Tz>() {
+                Ok(tz) => t.with_timezone(&tz).fixed_offset(),
+                Err(_) => t.fixed_offset(),
+            },
+            Self::Offset(secs) => match FixedOffset::east_opt(*secs) {
+                Some(offset) => t.with_timezone(&offset),
+                None => t.fixed_offset(),
+            },
+        }
+    }
+
+    /// The instant a local day starts
+    pub fn midnight(&self, date: NaiveDate) -> Option<DateTime<Utc>> {
+        let local = date.and_hms_opt(0, 0, 0)?;
+        match self {
+            Self::Named(name) => match name.parse::<Tz>() {
+                Ok(tz) => tz.from_local_datetime(&local).earliest().map(|t| t.to_utc()),
+                Err(_) => Some(local.and_utc()),
+            },
+            Self::Offset(secs) => FixedOffset::east_opt(*secs)?
+                .from_local_datetime(&local)
+                .single()
+                .map(|t| t.to_utc()),
+        }
+    }
+}
+
+/// The unit exported values of a metric are in. Motion is the fraction of each bucket with motion
+/// detected; light levels are converted to lux.
+pub fn unit(metric: Metric, temperature_unit: TemperatureUnit) -> &'static str {
+    match metric {
+        Metric::Motion => "fraction",
+        Metric::Temperature => temperature_unit.symbol(),
+        Metric::LightLevel => "lx",
+    }
+}
+
+/// One bucket of one sensor metric, with temperatures in °C
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportRow {
+    pub sensor: String,
+    pub device_id: String,
+    pub metric: Metric,
+    pub time: DateTime<Utc>,
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+}
+
+#[derive(Serialize)]
+struct JsonRow<'a> {
+    sensor: &'a str,
+    device_id: &'a str,
+    metric: &'static str,
+    unit: &'static str,
+    time: String,
+    min: f64,
+    avg: f64,
+    max: f64,
+}
+
+/// Quotes a CSV field if it holds a delimiter, quote or line break
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Formats export rows a batch at a time, so a long export can be streamed as it is read
+#[derive(Debug, Clone)]
+pub struct ExportWriter {
+    format: ExportFormat,
+    time_zone: ExportTimeZone,
+    temperature_unit: TemperatureUnit,
+    rows_written: usize,
+}
+
+impl ExportWriter {
+    pub fn new(
+        format: ExportFormat,
+        time_zone: ExportTimeZone,
+        temperature_unit: TemperatureUnit,
+    ) -> Self {
+        Self {
+            format,
+            time_zone,
+            temperature_unit,
+            rows_written: 0,
+        }
+    }
+
+    pub fn header(&self) -> String {
+        match self.format {
+            ExportFormat::Csv => "sensor,device_id,metric,unit,time,min,avg,max\n".to_string(),
+            ExportFormat::Json => "[".to_string(),
+        }
+    }
+
+    pub fn rows(&mut self, rows: &[ExportRow]) -> String {
+        let mut out = String::new();
+        for row in rows {
+            let time = self.time_zone.localize(row.time).to_rfc3339();
+            let unit = unit(row.metric, self.temperature_unit);
+            let (min, avg, max) = match row.metric {
+                Metric::Temperature => (
+                    self.temperature_unit.convert(row.min),
+                    self.temperature_unit.convert(row.avg),
+                    self.temperature_unit.convert(row.max),
+                ),
+                _ => (row.min, row.avg, row.max),
+            };
+            match self.format {
+                ExportFormat::Csv => {
+                    out.push_str(&format!(
+                        "{},{},{},{},{},{},{},{}\n",
+                        csv_field(&row.sensor),
+                        csv_field(&row.device_id),
+                        row.metric.as_str(),
+                        unit,
+                        time,
+                        min,
+                        avg,
+                        max
+                    ));
+                }
+                ExportFormat::Json => {
+                    let json = JsonRow {
+                        sensor: &row.sensor,
+                        device_id: &row.device_id,
+                        metric: row.metric.as_str(),
+                        unit,
+                        time,
+                        min,
+                        avg,
+                        max,
+                    };
+                    out.push_str(if self.rows_written == 0 { "\n" } else { ",\n" });
+                    out.push_str(&serde_json::to_string(&json).unwrap_or_default());
+                }
+            }
+            self.rows_written += 1;
+        }
+        out
+    }
+
+    pub fn footer(&self) -> String {
+        match self.format {
+            ExportFormat::Csv => String::new(),
+            ExportFormat::Json => "\n]\n".to_string(),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+#[cfg(feature = "server")]
+use std::sync::LazyLock;
+
+const DEFAULT_RETENTION_MINUTES: i64 = 15;
+
+/// How long each metric's in-memory history is kept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryRetention {
+    pub motion: Duration,
+    pub temperature: Duration,
+    pub light: Duration,
+}
+
+impl Default for HistoryRetention {
+    fn default() -> Self {
+        let retention = Duration::minutes(DEFAULT_RETENTION_MINUTES);
+        Self {
+            motion: retention,
+            temperature: retention,
+            light: retention,
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+static CONFIGURED_RETENTION: LazyLock<HistoryRetention> = LazyLock::new(HistoryRetention::from_env);
+
+impl HistoryRetention {
+    /// Reads `HISTORY_RETENTION_MINUTES`, overridden per metric by `MOTION_HISTORY_MINUTES`,
+    /// `TEMPERATURE_HISTORY_MINUTES` and `LIGHT_HISTORY_MINUTES`
+    #[cfg(feature = "server")]
+    pub fn from_env() -> Self {
+        let minutes = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse::<i64>().ok())
+                .filter(|m| *m > 0)
+                .map(Duration::minutes)
+        };
+        let all = minutes("HISTORY_RETENTION_MINUTES")
+            .unwrap_or(Duration::minutes(DEFAULT_RETENTION_MINUTES));
+
+        Self {
+            motion: minutes("MOTION_HISTORY_MINUTES").unwrap_or(all),
+            temperature: minutes("TEMPERATURE_HISTORY_MINUTES").unwrap_or(all),
+            light: minutes("LIGHT_HISTORY_MINUTES").unwrap_or(all),
+        }
+    }
+
+    /// The configured retention on the server, or the defaults in the browser
+    pub fn current() -> Self {
+        #[cfg(feature = "server")]
+        {
+            *CONFIGURED_RETENTION
+        }
+        #[cfg(not(feature = "server"))]
+        {
+            Self::default()
+        }
+    }
+
+    /// The longest retention of any metric
+    pub fn max(&self) -> Duration {
+        self.motion.max(self.temperature).max(self.light)
+    }
+}
+
+/// A time-bounded history of samples kept in timestamp order.
+///
+/// Samples usually arrive in order and are appended; late samples are inserted in place without
+/// re-sorting. One sample older than the retention window is kept as an anchor, so the value at
+/// the start of the window is always known.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct History<T> {
+    samples: VecDeque<(DateTime<Utc>, T)>,
+    retention_secs: i64,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self::new(Duration::minutes(DEFAULT_RETENTION_MINUTES))
+    }
+}
+
+impl<T> History<T> {
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            samples: VecDeque::new(),
+            retention_secs: retention.num_seconds(),
+        }
+    }
+
+    pub fn with_sample(retention: Duration, time: DateTime<Utc>, val: T) -> Self {
+        let mut history = Self::new(retention);
+        history.samples.push_back((time, val));
+        history
+    }
+
+    pub fn retention(&self) -> Duration {
+        Duration::seconds(self.retention_secs)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(DateTime<Utc>, T)> {
+        self.samples.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn last(&self) -> Option<&(DateTime<Utc>, T)> {
+        self.samples.back()
+    }
+
+    /// Adds a sample, ignoring duplicates of an existing timestamp, and prunes expired samples
+    pub fn insert(&mut self, time: DateTime<Utc>, val: T) {
+        let in_order = self.samples.back().map(|(t, _)| *t < time).unwrap_or(true);
+        if in_order {
+            self.samples.push_back((time, val));
+        } else {
+            let pos = self.samples.partition_point(|(t, _)| *t < time);
+            if self.samples.get(pos).map(|(t, _)| *t) != Some(time) {
+                self.samples.insert(pos, (time, val));
+            }
+        }
+
+        self.prune(Utc::now());
+    }
+
+    /// Drops samples older than the retention window, keeping the most recent one before the
+    /// cutoff as the anchor
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - self.retention();
+        let first_in_window = self.samples.partition_point(|(t, _)| *t < cutoff);
+        self.samples.drain(..first_in_window.saturating_sub(1));
+    }
+}
@@ -0,0 +1,448 @@
+use crate::hue::models::Metric;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Rows sent to the server, and written to the database, at a time
+pub const IMPORT_BATCH: usize = 2000;
+/// Errors listed in a report; any more are only counted
+const MAX_REPORTED_ERRORS: usize = 100;
+
+/// One reading read from an import file. `device` is whatever the file named the sensor by: a
+/// device or service id, a v1 sensor number, or the sensor's name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportRow {
+    pub line: usize,
+    pub time: DateTime<Utc>,
+    pub device: String,
+    pub metric: Metric,
+    pub value: f64,
+}
+
+impl ImportRow {
+    /// Checks the time is not in the future and the value is in range for the metric. Rows are
+    /// checked when parsed and again by the server, which can't trust what a client sends.
+    pub fn check(&self, now: DateTime<Utc>) -> Result<(), String> {
+        if self.time > now {
+            return Err(format!("{} is in the future", self.time.to_rfc3339()));
+        }
+        let valid = match self.metric {
+            Metric::Motion => self.value == 0.0 || self.value == 1.0,
+            Metric::Temperature => (-50.0..=100.0).contains(&self.value),
+            Metric::LightLevel => (0.0..=65535.0).contains(&self.value),
+        };
+        if !valid {
+            return Err(format!("{} is out of range for {}", self.value, self.metric.as_str()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImportError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// What an import read, wrote and skipped
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportReport {
+    /// Rows read from the file
+    pub rows: usize,
+    pub imported: u64,
+    /// Rows repeated in the file or already recorded
+    pub duplicates: usize,
+    /// Rows that failed to parse, validate or match a sensor
+    pub rejected: usize,
+    /// The first of the rejected rows' errors
+    pub errors: Vec<ImportError>,
+}
+
+impl ImportReport {
+    pub fn reject(&mut self, line: usize, message: impl Into<String>) {
+        self.rejected += 1;
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(ImportError {
+                line,
+                message: message.into(),
+            });
+        }
+    }
+
+    /// Adds the counts of a batch imported from the same file
+    pub fn merge(&mut self, batch: ImportReport) {
+        self.imported += batch.imported;
+        self.duplicates += batch.duplicates;
+        self.rejected += batch.rejected;
+        let room = MAX_REPORTED_ERRORS.saturating_sub(self.errors.len());
+        self.errors.extend(batch.errors.into_iter().take(room));
+    }
+}
+
+/// Parses an import file, keeping the rows that are valid and not repeated. CSV files need a
+/// header naming `timestamp`, `device`, `metric` and `value` columns, in any order. JSON files hold
+/// an array of objects, or one object per line, with the same fields; objects from the v1 logger,
+/// with a `sensor` number, `lastupdated` time and `state` object, are read too.
+///
+/// Timestamps are RFC 3339, UTC date-times like `2024-03-01 12:00:00`, or Unix seconds or
+/// milliseconds. Motion is `true`/`false` or 1/0, temperature in °C and light level in the bridge's
+/// raw units.
+pub fn parse(text: &str, now: DateTime<Utc>) -> (Vec<ImportRow>, ImportReport) {
+    let mut report = ImportReport::default();
+    let text = text.trim_start_matches('\u{feff}');
+    let records = if text.trim_start().starts_with(['[', '{']) {
+        json_records(text, &mut report)
+    } else {
+        csv_records(text, &mut report)
+    };
+
+    let mut seen = HashSet::new();
+    let mut rows = Vec::new();
+    for (line, record) in records {
+        report.rows += 1;
+        match record.and_then(|r| validate(r, line, now)) {
+            Ok(row) => {
+                let key = (row.device.to_lowercase(), row.metric, row.time);
+                if seen.insert(key) {
+                    rows.push(row);
+                } else {
+                    report.duplicates += 1;
+                }
+            }
+            Err(e) => report.reject(line, e),
+        }
+    }
+    (rows, report)
+}
+
+/// The fields of one record, before they are checked
+#[derive(Debug, Default)]
+struct Record {
+    timestamp: Option<Value>,
+    device: Option<String>,
+    metric: Option<String>,
+    value: Option<Value>,
+}
+
+type Records = Vec<(usize, Result<Record, String>)>;
+
+fn csv_records(text: &str, report: &mut ImportReport) -> Records {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !line.trim().is_empty());
+    let Some((header_line, header)) = lines.next() else {
+        return Vec::new();
+    };
+    let header: Vec<String> = csv_fields(header)
+        .iter()
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+    let column = |names: &[&str]| header.iter().position(|h| names.contains(&h.as_str()));
+    let (Some(timestamp), Some(device), Some(metric), Some(value)) = (
+        column(&["timestamp", "time"]),
+        column(&["device", "device_id", "sensor"]),
+        column(&["metric"]),
+        column(&["value"]),
+    ) else {
+        report.reject(
+            header_line,
+            "The header must name timestamp, device, metric and value columns",
+        );
+        return Vec::new();
+    };
+
+    lines
+        .map(|(line, text)| {
+            let fields = csv_fields(text);
+            let field = |i: usize| fields.get(i).map(|f| f.trim().to_string());
+            let record = Record {
+                timestamp: field(timestamp).map(Value::String),
+                device: field(device),
+                metric: field(metric),
+                value: field(value).map(Value::String),
+            };
+            (line, Ok(record))
+        })
+        .collect()
+}
+
+/// Splits a CSV line, unquoting quoted fields. Quoted fields can't span lines.
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn json_records(text: &str, report: &mut ImportReport) -> Records {
+    let objects: Vec<(usize, Result<Value, String>)> = if text.trim_start().starts_with('[') {
+        match serde_json::from_str::<Vec<Value>>(text) {
+            // Array elements are numbered from 1, as their lines aren't known
+            Ok(values) => values
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| (i + 1, Ok(v)))
+                .collect(),
+            Err(e) => {
+                report.reject(e.line(), format!("Invalid JSON: {}", e));
+                return Vec::new();
+            }
+        }
+    } else {
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let value = serde_json::from_str(line).map_err(|e| format!("Invalid JSON: {}", e));
+                (i + 1, value)
+            })
+            .collect()
+    };
+
+    objects
+        .into_iter()
+        .map(|(line, value)| (line, value.and_then(json_record)))
+        .collect()
+}
+
+fn json_record(value: Value) -> Result<Record, String> {
+    let Value::Object(object) = value else {
+        return Err("Expected an object".to_string());
+    };
+    let field = |names: &[&str]| names.iter().find_map(|n| object.get(*n)).cloned();
+    let text = |v: Value| match v {
+        Value::String(s) => s,
+        v => v.to_string(),
+    };
+
+    let mut record = Record {
+        timestamp: field(&["timestamp", "time", "lastupdated"]),
+        device: field(&["device", "device_id", "sensor"]).map(text),
+        metric: field(&["metric"]).map(text),
+        value: field(&["value"]),
+    };
+    // v1 logger entries carry the metric as a key of their state, with temperatures in 0.01 °C
+    if let (true, Some(Value::Object(state))) = (record.metric.is_none(), object.get("state")) {
+        let (metric, value) = if let Some(v) = state.get("presence") {
+            ("motion", v.clone())
+        } else if let Some(t) = state.get("temperature").and_then(Value::as_f64) {
+            ("temperature", Value::from(t / 100.0))
+        } else if let Some(v) = state.get("lightlevel") {
+            ("light_level", v.clone())
+        } else {
+            return Err("The state has no presence, temperature or lightlevel".to_string());
+        };
+        record.metric = Some(metric.to_string());
+        record.value = Some(value);
+        if record.timestamp.is_none() {
+            record.timestamp = state.get("lastupdated").cloned();
+        }
+    }
+    Ok(record)
+}
+
+fn validate(record: Record, line: usize, now: DateTime<Utc>) -> Result<ImportRow, String> {
+    let time = parse_timestamp(record.timestamp.ok_or("Missing timestamp")?)?;
+    let device = record.device.filter(|d| !d.is_empty()).ok_or("Missing device")?;
+    let metric_name = record.metric.ok_or("Missing metric")?;
+    let metric = match metric_name.trim().to_lowercase().as_str() {
+        "presence" => Metric::Motion,
+        "lightlevel" | "light" => Metric::LightLevel,
+        name => Metric::parse(name).ok_or_else(|| format!("Unknown metric {:?}", metric_name))?,
+    };
+    let value = parse_value(record.value.ok_or("Missing value")?)?;
+    let row = ImportRow {
+        line,
+        time,
+        device,
+        metric,
+        value,
+    };
+    row.check(now)?;
+    Ok(row)
+}
+
+fn parse_timestamp(value: Value) -> Result<DateTime<Utc>, String> {
+    let from_number = |n: i64| {
+        // Times after 1973 in milliseconds are larger than any plausible time in seconds
+        if n.abs() >= 100_000_000_000 {
+            DateTime::from_timestamp_millis(n)
+        } else {
+            DateTime::from_timestamp(n, 0)
+        }
+    };
+    let parsed = match &value {
+        Value::Number(n) => n.as_i64().and_then(from_number),
+        Value::String(s) => {
+            let s = s.trim();
+            DateTime::parse_from_rfc3339(s)
+                .map(|t| t.to_utc())
+                .ok()
+                .or_else(|| {
+                    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M"]
+                        .iter()
+                        .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+                        .map(|t| t.and_utc())
+                })
+                .or_else(|| s.parse().ok().and_then(from_number))
+        }
+        _ => None,
+    };
+    parsed.ok_or_else(|| format!("Invalid timestamp {}", value))
+}
+
+fn parse_value(value: Value) -> Result<f64, String> {
+    match &value {
+        Value::Bool(b) => Some(*b as i32 as f64),
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => match s.trim().to_lowercase().as_str() {
+            "true" => Some(1.0),
+            "false" => Some(0.0),
+            s => s.parse().ok(),
+        },
+        _ => None,
+    }
+    .filter(|v| v.is_finite())
+    .ok_or_else(|| format!("Invalid value {}", value))
+}
+
+/// Writes a batch of parsed rows to the history tables. Each row's device is matched to a sensor
+/// by device id, service id, v1 sensor number or name, and its metric to that sensor's service.
+/// Rows are checked again first, as they may come from a client. Rollups are rewound to the earliest new reading so maintenance summarises it.
+#[cfg(feature = "server")]
+pub async fn import_batch(
+    storage: &crate::hue::storage::Storage,
+    sensors: &[crate::hue::client::CompositeSensor],
+    rows: &[ImportRow],
+) -> Result<ImportReport, sqlx::Error> {
+    use crate::hue::storage::Reading;
+    use std::collections::HashMap;
+
+    // Names shared by several sensors map to `None`, as they're ambiguous
+    let mut by_key: HashMap<String, Option<&crate::hue::client::CompositeSensor>> = HashMap::new();
+    for sensor in sensors {
+        let v1_numbers = crate::hue::storage::v1_ids(sensor)
+            .into_iter()
+            .map(|(v1, _)| v1.to_string());
+        let keys = sensor
+            .resource_ids()
+            .cloned()
+            .chain(v1_numbers)
+            .chain([sensor.name.clone()]);
+        for key in keys {
+            by_key
+                .entry(key.trim().to_lowercase())
+                .and_modify(|s| {
+                    if s.is_some_and(|s| s.device_id != sensor.device_id) {
+                        *s = None;
+                    }
+                })
+                .or_insert(Some(sensor));
+        }
+    }
+
+    let now = Utc::now();
+    let mut report = ImportReport::default();
+    let mut readings = Vec::new();
+    for row in rows {
+        if let Err(e) = row.check(now) {
+            report.reject(row.line, e);
+            continue;
+        }
+        let key = row.device.trim().trim_start_matches("/sensors/").to_lowercase();
+        let sensor = match by_key.get(&key) {
+            Some(Some(sensor)) => sensor,
+            Some(None) => {
+                report.reject(row.line, format!("{:?} matches more than one sensor", row.device));
+                continue;
+            }
+            None => {
+                report.reject(row.line, format!("No sensor matches {:?}", row.device));
+                continue;
+            }
+        };
+        let resource_id = match row.metric {
+            Metric::Motion => sensor.motion.as_ref().map(|m| &m.id),
+            Metric::Temperature => sensor.temperature.as_ref().map(|t| &t.id),
+            Metric::LightLevel => sensor.light.as_ref().map(|l| &l.id),
+        };
+        let Some(resource_id) = resource_id else {
+            report.reject(
+                row.line,
+                format!("{} has no {} service", sensor.name, row.metric.as_str()),
+            );
+            continue;
+        };
+        readings.push(Reading {
+            resource_id: resource_id.clone(),
+            device_id: sensor.device_id.clone(),
+            metric: row.metric,
+            time: row.time,
+            value: row.value,
+        });
+    }
+
+    report.imported = storage.insert_readings(&readings).await?;
+    report.duplicates = readings.len() - report.imported as usize;
+    if report.imported > 0 {
+        let mut earliest: HashMap<&str, DateTime<Utc>> = HashMap::new();
+        for r in &readings {
+            let t = earliest.entry(&r.resource_id).or_insert(r.time);
+            *t = (*t).min(r.time);
+        }
+        for (resource_id, t) in earliest {
+            storage.rewind_rollups(resource_id, t).await?;
+        }
+    }
+    Ok(report)
+}
+
+/// `huebot import <file>`: imports a file of readings, matching devices to the bridge's sensors,
+/// and prints progress and the rows it skipped
+#[cfg(feature = "server")]
+pub async fn import_file(path: &str) -> Result<ImportReport, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+    let storage = crate::hue::storage::connect_from_env().await?;
+    if crate::hue::storage::auto_migrate() {
+        storage.migrate().await.map_err(|e| e.to_string())?;
+    }
+    let sensors = crate::hue::get_hue_client()
+        .get_sensors(&std::collections::HashMap::new())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let (rows, mut report) = parse(&text, Utc::now());
+    let mut written = 0;
+    for batch in rows.chunks(IMPORT_BATCH) {
+        let batch_report = import_batch(&storage, &sensors, batch)
+            .await
+            .map_err(|e| e.to_string())?;
+        report.merge(batch_report);
+        written += batch.len();
+        println!("{}/{} rows", written, rows.len());
+    }
+
+    for error in &report.errors {
+        println!("Line {}: {}", error.line, error.message);
+    }
+    if report.rejected > report.errors.len() {
+        println!("…and {} more errors", report.rejected - report.errors.len());
+    }
+    Ok(report)
+}
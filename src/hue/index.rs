@@ -0,0 +1,61 @@
+use crate::hue::events::HueEvent;
+use crate::hue::models::{CompositeSensor, SensorUpdate};
+use std::collections::HashMap;
+
+/// Maps device ids (event owners) and service resource ids to the position of the owning sensor
+/// in a `CompositeSensor` list, so events can be routed without scanning every sensor.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SensorIndex {
+    by_rid: HashMap<String, usize>,
+}
+
+impl SensorIndex {
+    pub fn new(sensors: &[CompositeSensor]) -> Self {
+        let mut index = Self::default();
+        for (i, sensor) in sensors.iter().enumerate() {
+            index.insert(i, sensor);
+        }
+        index
+    }
+
+    /// Registers the device id and every service id of the sensor at position `i`
+    pub fn insert(&mut self, i: usize, sensor: &CompositeSensor) {
+        for id in sensor.resource_ids() {
+            self.by_rid.insert(id.clone(), i);
+        }
+    }
+
+    /// Finds the sensor an event belongs to, matching the owner (device) first and then the resource id
+    pub fn lookup(&self, event: &HueEvent) -> Option<usize> {
+        event
+            .owner_rid()
+            .filter(|rid| !rid.is_empty())
+            .and_then(|rid| self.by_rid.get(rid))
+            .or_else(|| event.resource_id().and_then(|rid| self.by_rid.get(rid)))
+            .copied()
+    }
+
+    /// Applies an event to the sensor it belongs to, returning that sensor's position if one matched
+    pub fn apply(&mut self, sensors: &mut [CompositeSensor], event: &HueEvent) -> Option<usize> {
+        let i = self.lookup(event)?;
+        let sensor = sensors.get_mut(i)?;
+        sensor.apply_event(event);
+        // The event may have introduced a new service on the device
+        self.insert(i, sensor);
+        Some(i)
+    }
+
+    /// Applies a server update (already calibrated) to the sensor it belongs to
+    pub fn apply_update(
+        &mut self,
+        sensors: &mut [CompositeSensor],
+        update: &SensorUpdate,
+    ) -> Option<usize> {
+        let event = update.to_event();
+        let i = self.lookup(&event)?;
+        let sensor = sensors.get_mut(i)?;
+        sensor.merge_event(&event);
+        self.insert(i, sensor);
+        Some(i)
+    }
+}
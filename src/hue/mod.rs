@@ -2,18 +2,31 @@
 use chrono::Utc;
 #[cfg(feature = "server")]
 use futures::StreamExt;
-#[cfg(feature = "server")]
-use sqlx::PgPool;
 use std::sync::LazyLock;
 #[cfg(feature = "server")]
 use tokio::sync::OnceCell;
 
+pub mod aggregate;
 pub mod client;
 #[cfg(feature = "server")]
 pub mod eventcache;
 pub mod events;
+pub mod export;
+pub mod history;
+pub mod import;
+pub mod index;
 pub mod models;
 #[cfg(feature = "server")]
+pub mod recorder;
+#[cfg(feature = "server")]
+pub mod retention;
+#[cfg(feature = "server")]
+pub mod settings;
+pub mod stats;
+#[cfg(feature = "server")]
+pub mod storage;
+pub mod subscription;
+#[cfg(feature = "server")]
 pub mod tests;
 
 #[cfg(feature = "server")]
@@ -44,20 +57,24 @@ pub fn get_hue_client() -> &'static client::ClientEx {
 }
 
 #[cfg(feature = "server")]
-static DB_POOL: OnceCell<PgPool> = OnceCell::const_new();
+static STORAGE: OnceCell<storage::Storage> = OnceCell::const_new();
 
 #[cfg(feature = "server")]
 static SENSORS_CACHE: tokio::sync::RwLock<
-    Option<(Vec<client::CompositeSensor>, chrono::DateTime<Utc>)>,
+    Option<(
+        Vec<client::CompositeSensor>,
+        index::SensorIndex,
+        chrono::DateTime<Utc>,
+    )>,
 > = tokio::sync::RwLock::const_new(None);
 
 #[cfg(feature = "server")]
 pub async fn get_sensors_cached() -> Result<Vec<client::CompositeSensor>, ServerFnError> {
     let mut sensors = {
         let cache = SENSORS_CACHE.read().await;
-        if let Some((sensors, timestamp)) = &*cache {
+        if let Some((sensors, index, timestamp)) = &*cache {
             if (Utc::now() - *timestamp).num_minutes() < 5 {
-                Some(sensors.clone())
+                Some((sensors.clone(), index.clone()))
             } else {
                 None
             }
@@ -69,72 +86,134 @@ pub async fn get_sensors_cached() -> Result<Vec<client::CompositeSensor>, Server
     if sensors.is_none() {
         // Cache miss or expired
         let fresh_sensors: Vec<client::CompositeSensor> = get_hue_client()
-            .get_sensors()
+            .get_sensors(&SENSOR_SETTINGS.all())
             .await
             .map_err(|e| ServerFnError::new(e))?;
+        let fresh_index = index::SensorIndex::new(&fresh_sensors);
         let mut cache = SENSORS_CACHE.write().await;
-        *cache = Some((fresh_sensors.clone(), Utc::now()));
-        sensors = Some(fresh_sensors);
+        // Diffs only carry readings, so clients refetch when sensors come, go or move
+        let changed = cache
+            .as_ref()
+            .is_some_and(|(old, _, _)| sensor_layout(old) != sensor_layout(&fresh_sensors));
+        *cache = Some((fresh_sensors.clone(), fresh_index.clone(), Utc::now()));
+        drop(cache);
+        if changed {
+            let _ = SENSOR_UPDATE_CHANNEL.send(events::SensorStreamMessage::Resync);
+        }
+        sensors = Some((fresh_sensors, fresh_index));
     }
 
-    let mut sensors = sensors.unwrap();
+    let (mut sensors, mut index) = sensors.unwrap();
 
     // Backfill history from EventCache
-    let events = EVENT_CACHE.get_all();
-    for event_str in events {
-        if let Ok(v) = serde_json::from_str::<serde_json::Value>(&event_str) {
-            if let Some(event) = client::HueEvent::from_json(&v) {
-                let owner_rid = event.owner_rid();
-                let resource_id = event.resource_id();
-
-                for s in sensors.iter_mut() {
-                    let is_owner = owner_rid == Some(s.device_id.as_str());
-                    let matches_resource = resource_id.is_some()
-                        && (s.motion.as_ref().map(|m| m.id.as_str()) == resource_id
-                            || s.temperature.as_ref().map(|t| t.id.as_str()) == resource_id
-                            || s.light.as_ref().map(|l| l.id.as_str()) == resource_id);
-
-                    if is_owner || matches_resource {
-                        s.apply_event(&event);
-                    }
-                }
-            }
-        }
+    for cached in EVENT_CACHE.get_all() {
+        index.apply(&mut sensors, &cached.event);
     }
 
     Ok(sensors)
 }
 
+/// What identifies each sensor and where it is shown, leaving out its readings
 #[cfg(feature = "server")]
-pub async fn get_db_pool() -> Result<PgPool, ServerFnError> {
-    DB_POOL
+fn sensor_layout(
+    sensors: &[client::CompositeSensor],
+) -> Vec<(Vec<&String>, &String, &Option<client::SensorGroup>, &Vec<client::SensorGroup>)> {
+    sensors
+        .iter()
+        .map(|s| (s.resource_ids().collect(), &s.name, &s.room, &s.zones))
+        .collect()
+}
+
+/// Whether a history database is configured. Without one, graphs fall back to the history each
+/// sensor keeps in memory.
+#[cfg(feature = "server")]
+pub fn storage_configured() -> bool {
+    std::env::var("DATABASE_URL").is_ok()
+}
+
+/// The history database from `DATABASE_URL` (`postgres://…` or `sqlite:…`), migrated on first use
+#[cfg(feature = "server")]
+pub async fn get_storage() -> Result<storage::Storage, ServerFnError> {
+    STORAGE
         .get_or_try_init(|| async {
             let db_url = std::env::var("DATABASE_URL")
                 .map_err(|_| ServerFnError::new("DATABASE_URL must be set"))?;
-            PgPool::connect(&db_url)
+            let storage = storage::Storage::connect(&db_url, 5)
                 .await
-                .map_err(|e| ServerFnError::new(e.to_string()))
+                .map_err(|e| ServerFnError::new(e.to_string()))?;
+            if storage::auto_migrate() {
+                storage
+                    .migrate()
+                    .await
+                    .map_err(|e| ServerFnError::new(format!("Database migration failed: {}", e)))?;
+            }
+            Ok(storage)
         })
         .await
         .cloned()
 }
 
 #[cfg(feature = "server")]
-static EVENT_CHANNEL: LazyLock<tokio::sync::broadcast::Sender<String>> = LazyLock::new(|| {
-    let (tx, _) = tokio::sync::broadcast::channel(100);
+fn env_usize(name: &str) -> Option<usize> {
+    std::env::var(name).ok().and_then(|v| v.trim().parse().ok())
+}
+
+/// Number of events buffered for subscribers (`EVENT_CHANNEL_CAPACITY`, default 100)
+#[cfg(feature = "server")]
+static EVENT_CHANNEL: LazyLock<tokio::sync::broadcast::Sender<events::CachedEvent>> = LazyLock::new(|| {
+    let capacity = env_usize("EVENT_CHANNEL_CAPACITY").unwrap_or(100).max(1);
+    let (tx, _) = tokio::sync::broadcast::channel(capacity);
     tx
 });
 
+/// How many unsent events a single client may fall behind by before it is skipped to the live edge
+/// and told it lagged (`EVENT_CLIENT_MAX_PENDING`, default: the channel capacity)
+#[cfg(feature = "server")]
+static EVENT_CLIENT_MAX_PENDING: LazyLock<Option<usize>> =
+    LazyLock::new(|| env_usize("EVENT_CLIENT_MAX_PENDING").filter(|n| *n > 0));
+
+/// Sensor-state diffs produced by merging bridge events into `SENSORS_CACHE`, and resyncs when
+/// the cache is rebuilt
+#[cfg(feature = "server")]
+static SENSOR_UPDATE_CHANNEL: LazyLock<tokio::sync::broadcast::Sender<events::SensorStreamMessage>> =
+    LazyLock::new(|| {
+        let capacity = env_usize("EVENT_CHANNEL_CAPACITY").unwrap_or(100).max(1);
+        let (tx, _) = tokio::sync::broadcast::channel(capacity);
+        tx
+    });
+
+/// Per-sensor settings, stored in `SENSOR_SETTINGS_PATH` (default `sensor_settings.json`)
+#[cfg(feature = "server")]
+static SENSOR_SETTINGS: LazyLock<settings::SettingsStore> = LazyLock::new(|| {
+    let path = std::env::var("SENSOR_SETTINGS_PATH")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| "sensor_settings.json".to_string());
+    settings::SettingsStore::open(path.trim())
+});
+
 #[cfg(feature = "server")]
 static EVENT_CACHE: LazyLock<eventcache::EventCache> =
-    LazyLock::new(|| eventcache::EventCache::new(30));
+    LazyLock::new(|| {
+        // Keep at least as much as the in-memory sensor history so it can be backfilled
+        let retention = history::HistoryRetention::current().max().num_minutes();
+        let max_age = retention.max(30);
+        match std::env::var("EVENT_CACHE_PATH") {
+            Ok(path) if !path.trim().is_empty() => {
+                eventcache::EventCache::with_log(max_age, path.trim())
+            }
+            _ => eventcache::EventCache::new(max_age),
+        }
+    });
 
 #[cfg(feature = "server")]
 static EVENT_LOOP_STARTED: std::sync::atomic::AtomicBool =
     std::sync::atomic::AtomicBool::new(false);
 
+/// Connects to the bridge's event stream, once, on the calling runtime. Events update
+/// `SENSORS_CACHE`, are cached and are broadcast on `EVENT_CHANNEL` to clients and the recorder.
 #[cfg(feature = "server")]
-fn start_event_listener() {
+pub fn start_event_listener() {
     if !EVENT_LOOP_STARTED.load(std::sync::atomic::Ordering::Relaxed) {
         if !EVENT_LOOP_STARTED.swap(true, std::sync::atomic::Ordering::SeqCst) {
             tokio::spawn(async move {
@@ -148,39 +227,28 @@ fn start_event_listener() {
                             println!("Connected to Hue Bridge event stream.");
                             futures::pin_mut!(stream);
                             while let Some(msg) = stream.next().await {
-                                EVENT_CACHE.add(msg.clone());
-
-                                // Update sensor cache
                                 if let Ok(v) = serde_json::from_str::<serde_json::Value>(&msg) {
                                     if let Some(event) = client::HueEvent::from_json(&v) {
-                                        let mut cache = SENSORS_CACHE.write().await;
-                                        if let Some((ref mut sensors, _)) = *cache {
-                                            let owner_rid = event.owner_rid();
-                                            let resource_id = event.resource_id();
-
-                                            for s in sensors.iter_mut() {
-                                                let is_owner =
-                                                    owner_rid == Some(s.device_id.as_str());
-                                                let matches_resource = resource_id.is_some()
-                                                    && (s.motion.as_ref().map(|m| m.id.as_str())
-                                                        == resource_id
-                                                        || s.temperature
-                                                            .as_ref()
-                                                            .map(|t| t.id.as_str())
-                                                            == resource_id
-                                                        || s.light.as_ref().map(|l| l.id.as_str())
-                                                            == resource_id);
-
-                                                if is_owner || matches_resource {
-                                                    s.apply_event(&event);
+                                        // Update sensor cache
+                                        {
+                                            let mut cache = SENSORS_CACHE.write().await;
+                                            if let Some((ref mut sensors, ref mut index, _)) = *cache {
+                                                if let Some(i) = index.apply(sensors, &event) {
+                                                    if let Some(update) =
+                                                        models::SensorUpdate::from_applied(&sensors[i], &event)
+                                                    {
+                                                        let _ = SENSOR_UPDATE_CHANNEL
+                                                            .send(events::SensorStreamMessage::Update(update));
+                                                    }
                                                 }
                                             }
                                         }
+
+                                        // Cache (assigning a sequence number) and broadcast
+                                        let cached = EVENT_CACHE.add(event, msg);
+                                        let _ = tx.send(cached);
                                     }
                                 }
-
-                                // Broadcast raw message
-                                let _ = tx.send(msg);
                             }
                             println!("Hue Bridge event stream ended.");
                         }
@@ -196,133 +264,176 @@ fn start_event_listener() {
 }
 
 use dioxus::prelude::*;
-
+pub use subscription::{
+    use_connection_state, use_hue_event_handler, use_sensor_updates, ConnectionState,
+};
+
+/// Streams bridge events as newline-delimited [`events::StreamMessage`]s.
+///
+/// A reconnecting client passes the cursor of the last message it saw as `since` and receives
+/// exactly the events it missed, or a `ResyncRequired` marker if they are no longer cached or the
+/// server has restarted since. Without a cursor,
+/// `cached` controls whether the whole event cache is replayed first.
+///
+/// Only events passing `filter` are sent, so clients don't receive traffic they won't render.
 #[server(output = StreamingText)]
-pub async fn hue_events(cached: bool) -> Result<dioxus::fullstack::TextStream, ServerFnError> {
+pub async fn hue_events(
+    cached: bool,
+    since: Option<events::EventCursor>,
+    filter: events::EventFilter,
+) -> Result<dioxus::fullstack::TextStream, ServerFnError> {
+    use events::StreamMessage;
+
     start_event_listener();
 
+    let members = resolve_filter_members(&filter).await?;
+
+    // Subscribe before taking the replay so nothing falls between the two
     let tx = &EVENT_CHANNEL;
     let rx = tx.subscribe();
+    let replay = EVENT_CACHE.replay(since, cached);
+    let latest = replay.latest;
 
-    let cached_stream = if cached {
-        let cached = EVENT_CACHE.get_all();
-        futures::stream::iter(cached)
-    } else {
-        futures::stream::iter(Vec::new())
-    };
+    let mut head = Vec::new();
+    if replay.resync_required {
+        head.push(StreamMessage::ResyncRequired.to_line());
+    }
+    head.extend(
+        replay
+            .events
+            .into_iter()
+            .filter(|e| filter.matches(&e.event, members.as_ref()))
+            .map(|e| StreamMessage::Event(e).to_line()),
+    );
+    head.push(StreamMessage::Cursor(latest).to_line());
 
-    let stream = futures::stream::unfold(rx, |mut rx| async move {
+    let stream = live_events(rx, latest.seq, filter, members, *EVENT_CLIENT_MAX_PENDING);
+
+    Ok(dioxus::fullstack::TextStream::new(
+        futures::stream::iter(head).chain(stream),
+    ))
+}
+
+/// The live part of a `hue_events` stream: events after `after_seq` that pass the filter, as
+/// [`events::StreamMessage`] lines. A client more than `max_pending` events behind, or one the
+/// channel dropped events for, is sent `Lagged` and skipped ahead.
+#[cfg(feature = "server")]
+pub(crate) fn live_events(
+    rx: tokio::sync::broadcast::Receiver<events::CachedEvent>,
+    after_seq: u64,
+    filter: events::EventFilter,
+    members: Option<std::collections::HashSet<String>>,
+    max_pending: Option<usize>,
+) -> impl futures::Stream<Item = String> {
+    use events::StreamMessage;
+
+    let state = (rx, filter, members);
+    futures::stream::unfold(state, move |(mut rx, filter, members)| async move {
         loop {
+            // The stream is only polled as fast as the client reads, so a backlog here means a slow client
+            if let Some(max_pending) = max_pending {
+                if rx.len() > max_pending {
+                    let skipped = rx.len() as u64;
+                    println!("Event stream client fell {} events behind; skipping ahead", skipped);
+                    rx = rx.resubscribe();
+                    let msg = StreamMessage::Lagged { skipped }.to_line();
+                    return Some((msg, (rx, filter, members)));
+                }
+            }
+
             match rx.recv().await {
-                Ok(msg) => return Some((msg, rx)),
-                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
-                    continue;
+                // Skip anything already covered by the replay
+                Ok(event) if event.seq <= after_seq => continue,
+                Ok(event) if !filter.matches(&event.event, members.as_ref()) => continue,
+                Ok(event) => {
+                    let msg = StreamMessage::Event(event).to_line();
+                    return Some((msg, (rx, filter, members)));
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    println!("Event stream client lagged by {} events", skipped);
+                    let msg = StreamMessage::Lagged { skipped }.to_line();
+                    return Some((msg, (rx, filter, members)));
                 }
                 Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
             }
         }
-    });
+    })
+}
 
-    Ok(dioxus::fullstack::TextStream::new(
-        cached_stream.chain(stream),
-    ))
+/// Returns every sensor from the server's sensor cache
+#[server]
+pub async fn get_sensors() -> Result<Vec<client::CompositeSensor>, ServerFnError> {
+    get_sensors_cached().await
 }
 
-pub fn use_hue_event_handler(
-    cached: bool,
-    on_event: impl FnMut(client::HueEvent) + 'static,
-    on_error: impl FnMut(String) + 'static,
-) {
-    use std::cell::RefCell;
-    use std::rc::Rc;
-
-    let on_event = Rc::new(RefCell::new(Some(on_event)));
-    let on_error = Rc::new(RefCell::new(Some(on_error)));
-
-    let mut is_visible = use_signal(|| {
-        #[cfg(feature = "web")]
-        {
-            web_sys::window()
-                .and_then(|w| w.document())
-                .map(|d| !d.hidden())
-                .unwrap_or(true)
-        }
-        #[cfg(not(feature = "web"))]
-        {
-            true
-        }
-    });
+/// Streams changes to the server's sensor cache as newline-delimited [`events::SensorStreamMessage`]s.
+///
+/// Each update is the merged state of one metric after an event was applied, so a client that
+/// applies them to a `get_sensors` result stays identical to the server cache. The stream opens with
+/// a `Resync` (anything between the client's fetch and connecting would otherwise be missed) and
+/// sends another whenever the client lags, the settings change, or a rebuild of the cache finds
+/// sensors added, removed or moved.
+#[server(output = StreamingText)]
+pub async fn sensor_updates() -> Result<dioxus::fullstack::TextStream, ServerFnError> {
+    use events::SensorStreamMessage;
 
-    let _listener = use_hook(|| {
-        #[cfg(feature = "web")]
-        {
-            let document = web_sys::window().unwrap().document().unwrap();
-            Rc::new(gloo_events::EventListener::new(
-                &document,
-                "visibilitychange",
-                move |_| {
-                    let hidden = web_sys::window()
-                        .and_then(|w| w.document())
-                        .map(|d| d.hidden())
-                        .unwrap_or(false);
-                    is_visible.set(!hidden);
-                },
-            ))
-        }
-        #[cfg(not(feature = "web"))]
-        {
-            Rc::new(())
+    start_event_listener();
+
+    let rx = SENSOR_UPDATE_CHANNEL.subscribe();
+    let head = futures::stream::iter([SensorStreamMessage::Resync.to_line()]);
+    let stream = futures::stream::unfold(rx, |mut rx| async move {
+        match rx.recv().await {
+            Ok(msg) => Some((msg.to_line(), rx)),
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                println!("Sensor update client lagged by {} updates", skipped);
+                Some((SensorStreamMessage::Resync.to_line(), rx))
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => None,
         }
     });
 
-    use_resource(move || {
-        let on_event = on_event.clone();
-        let on_error = on_error.clone();
-        let visible = is_visible();
+    Ok(dioxus::fullstack::TextStream::new(head.chain(stream)))
+}
 
-        async move {
-            #[cfg(feature = "web")]
-            {
-                if !visible {
-                    return;
-                }
+/// Returns the stored settings for a sensor (the defaults if none were saved)
+#[server]
+pub async fn get_sensor_settings(device_id: String) -> Result<models::SensorSettings, ServerFnError> {
+    Ok(SENSOR_SETTINGS.get(&device_id))
+}
 
-                loop {
-                    match hue_events(cached).await {
-                        Ok(mut stream) => {
-                            while let Some(Ok(event_str)) = stream.next().await {
-                                for v in serde_json::Deserializer::from_str(&event_str)
-                                    .into_iter::<serde_json::Value>()
-                                {
-                                    if let Ok(v) = v {
-                                        if let Some(event) = client::HueEvent::from_json(&v) {
-                                            if let Some(ref mut handler) = *on_event.borrow_mut() {
-                                                handler(event);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            let msg = e.to_string();
-                            if let Some(ref mut handler) = *on_error.borrow_mut() {
-                                handler(msg);
-                            }
-                        }
-                    }
-                    gloo_timers::future::TimeoutFuture::new(1000).await;
-
-                    // Re-check visibility before looping
-                    if !web_sys::window()
-                        .and_then(|w| w.document())
-                        .map(|d| !d.hidden())
-                        .unwrap_or(true)
-                    {
-                        break;
-                    }
-                }
-            }
-        }
-    });
+/// Saves a sensor's settings and rebuilds the sensor cache with them
+#[server]
+pub async fn save_sensor_settings(
+    device_id: String,
+    settings: models::SensorSettings,
+) -> Result<(), ServerFnError> {
+    SENSOR_SETTINGS
+        .set(&device_id, settings)
+        .map_err(|e| ServerFnError::new(format!("Unable to save sensor settings: {}", e)))?;
+
+    // Calibration applies to every reading, so rebuild from the bridge rather than patching in place
+    *SENSORS_CACHE.write().await = None;
+    let _ = SENSOR_UPDATE_CHANNEL.send(events::SensorStreamMessage::Resync);
+    Ok(())
+}
+
+/// Resolves the device and room selections of a filter into the set of matching resource ids,
+/// from the sensors' rooms and zones like the client does
+#[cfg(feature = "server")]
+async fn resolve_filter_members(
+    filter: &events::EventFilter,
+) -> Result<Option<std::collections::HashSet<String>>, ServerFnError> {
+    if filter.rooms.is_empty() {
+        return Ok(filter.members(&[]));
+    }
+    Ok(filter.members(&get_sensors_cached().await?))
+}
+
+/// Queries the server's recent event cache, e.g. the last 10 minutes of events for one device
+#[server]
+pub async fn query_events(
+    query: events::EventQuery,
+) -> Result<Vec<events::CachedEvent>, ServerFnError> {
+    start_event_listener();
+    Ok(EVENT_CACHE.query(&query))
 }
@@ -1,8 +1,59 @@
 use crate::hue::events::HueEvent;
-use chrono::{DateTime, Utc};
+use crate::hue::history::{History, HistoryRetention};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
+/// Raw light level at or below which the bridge considers it dark (v1 `tholddark` default)
+pub const LIGHT_LEVEL_DARK_THRESHOLD: i32 = 16000;
+/// Offset above the dark threshold at which the bridge considers it daylight (v1 `tholdoffset` default)
+pub const LIGHT_LEVEL_DAYLIGHT_OFFSET: i32 = 7000;
+
+/// How often a temperature report is expected. Motion only reports on change, so has no interval.
+pub const TEMPERATURE_REPORT_INTERVAL_MINUTES: i64 = 15;
+/// How often a light level report is expected
+pub const LIGHT_REPORT_INTERVAL_MINUTES: i64 = 15;
+/// Expected intervals that may pass without a report before a sensor is considered stale
+const STALE_AFTER_INTERVALS: i32 = 2;
+/// Expected intervals that may pass without a report before a sensor is considered silent
+const SILENT_AFTER_INTERVALS: i32 = 8;
+
+/// Whether a sensor is still reporting as often as expected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum SensorHealth {
+    Fresh,
+    /// A few reports were missed
+    Stale,
+    /// No reports for a long time; the battery is probably dead or the sensor out of range
+    Silent,
+}
+
+impl SensorHealth {
+    pub fn from_age(age: Duration, expected_interval: Duration) -> Self {
+        if age > Self::silent_after(expected_interval) {
+            Self::Silent
+        } else if age > expected_interval * STALE_AFTER_INTERVALS {
+            Self::Stale
+        } else {
+            Self::Fresh
+        }
+    }
+
+    /// How long a sensor reporting every `expected_interval` may go without a report before it is
+    /// considered silent
+    pub fn silent_after(expected_interval: Duration) -> Duration {
+        expected_interval * SILENT_AFTER_INTERVALS
+    }
+}
+
+/// Converts the bridge's logarithmic light level (10000*log10(lux) + 1) into lux
+pub fn light_level_to_lux(light_level: i32) -> f64 {
+    if light_level <= 0 {
+        return 0.0;
+    }
+    10f64.powf((light_level - 1) as f64 / 10000.0)
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MotionData {
     pub id: String,
@@ -11,7 +62,7 @@ pub struct MotionData {
     pub presence: bool,
     pub last_updated: DateTime<Utc>,
     #[serde(default)]
-    pub history: Arc<Vec<(DateTime<Utc>, bool)>>,
+    pub history: Arc<History<bool>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -22,7 +73,7 @@ pub struct TemperatureData {
     pub temperature: f64,
     pub last_updated: DateTime<Utc>,
     #[serde(default)]
-    pub history: Arc<Vec<(DateTime<Utc>, f64)>>,
+    pub history: Arc<History<f64>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -31,9 +82,239 @@ pub struct LightData {
     pub id_v1: Option<String>,
     pub enabled: bool,
     pub light_level: i32,
+    #[serde(default)]
+    pub lux: f64,
+    #[serde(default)]
+    pub dark: bool,
+    #[serde(default)]
+    pub daylight: bool,
     pub last_updated: DateTime<Utc>,
     #[serde(default)]
-    pub history: Arc<Vec<(DateTime<Utc>, i32)>>,
+    pub history: Arc<History<i32>>,
+}
+
+impl LightData {
+    pub fn new(
+        id: String,
+        id_v1: Option<String>,
+        enabled: bool,
+        light_level: i32,
+        last_updated: DateTime<Utc>,
+    ) -> Self {
+        let mut light = Self {
+            id,
+            id_v1,
+            enabled,
+            light_level,
+            lux: 0.0,
+            dark: false,
+            daylight: false,
+            last_updated,
+            history: Arc::new(History::with_sample(
+                HistoryRetention::current().light,
+                last_updated,
+                light_level,
+            )),
+        };
+        light.set_light_level(light_level);
+        light
+    }
+
+    /// Sets the raw light level and recomputes lux and the dark/daylight flags
+    pub fn set_light_level(&mut self, light_level: i32) {
+        self.light_level = light_level;
+        self.lux = light_level_to_lux(light_level);
+        self.dark = light_level <= LIGHT_LEVEL_DARK_THRESHOLD;
+        self.daylight = light_level >= LIGHT_LEVEL_DARK_THRESHOLD + LIGHT_LEVEL_DAYLIGHT_OFFSET;
+    }
+}
+
+/// A kind of sensor reading, as stored in the history tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Motion,
+    Temperature,
+    LightLevel,
+}
+
+impl Metric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Motion => "motion",
+            Self::Temperature => "temperature",
+            Self::LightLevel => "light_level",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "motion" => Some(Self::Motion),
+            "temperature" => Some(Self::Temperature),
+            "light_level" => Some(Self::LightLevel),
+            _ => None,
+        }
+    }
+}
+
+/// The new value of a single sensor metric
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SensorMetric {
+    Motion(bool),
+    Temperature(f64),
+    LightLevel(i32),
+}
+
+/// A change to one metric of a sensor, as merged into the server's sensor cache
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SensorUpdate {
+    pub device_id: String,
+    pub resource_id: String,
+    pub metric: SensorMetric,
+    pub enabled: bool,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl SensorUpdate {
+    /// Describes the state of `sensor` after `event` has been applied to it
+    pub fn from_applied(sensor: &CompositeSensor, event: &HueEvent) -> Option<Self> {
+        let (resource_id, metric, enabled, timestamp) = match event {
+            HueEvent::Motion { .. } => {
+                let m = sensor.motion.as_ref()?;
+                (&m.id, SensorMetric::Motion(m.presence), m.enabled, m.last_updated)
+            }
+            HueEvent::Temperature { .. } => {
+                let t = sensor.temperature.as_ref()?;
+                (
+                    &t.id,
+                    SensorMetric::Temperature(t.temperature),
+                    t.enabled,
+                    t.last_updated,
+                )
+            }
+            HueEvent::LightLevel { .. } => {
+                let l = sensor.light.as_ref()?;
+                (
+                    &l.id,
+                    SensorMetric::LightLevel(l.light_level),
+                    l.enabled,
+                    l.last_updated,
+                )
+            }
+            HueEvent::Raw(_) => return None,
+        };
+
+        Some(Self {
+            device_id: sensor.device_id.clone(),
+            resource_id: resource_id.clone(),
+            metric,
+            enabled,
+            timestamp,
+        })
+    }
+
+    /// The equivalent event, so clients merge updates exactly as the server cache does
+    pub fn to_event(&self) -> HueEvent {
+        let id = self.resource_id.clone();
+        let owner_rid = self.device_id.clone();
+        let changed = self.timestamp;
+        let enabled = self.enabled;
+        match self.metric {
+            SensorMetric::Motion(presence) => HueEvent::Motion {
+                id,
+                owner_rid,
+                presence,
+                changed,
+                enabled,
+            },
+            SensorMetric::Temperature(temperature) => HueEvent::Temperature {
+                id,
+                owner_rid,
+                temperature,
+                changed,
+                enabled,
+            },
+            SensorMetric::LightLevel(light_level) => HueEvent::LightLevel {
+                id,
+                owner_rid,
+                light_level,
+                changed,
+                enabled,
+            },
+        }
+    }
+}
+
+/// User overrides for a sensor, kept by huebot rather than the bridge
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SensorSettings {
+    /// Overrides the guess from the product name
+    pub is_outdoor: Option<bool>,
+    /// Emoji shown on the sensor card; guessed from the name if unset
+    pub icon: Option<String>,
+    /// Sensors with an order sort first (lowest first), before the default outdoor/name ordering
+    pub display_order: Option<i32>,
+    pub tags: Vec<String>,
+    /// Added to every temperature reading, in °C
+    pub temperature_offset: f64,
+}
+
+/// A room or zone from the bridge topology
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SensorGroup {
+    pub id: String,
+    pub name: String,
+}
+
+/// Current state of the sensors in a room, for its heading
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GroupSummary {
+    /// Sensors currently detecting motion
+    pub occupied: usize,
+    /// Enabled motion sensors
+    pub motion_sensors: usize,
+    pub avg_temperature: Option<f64>,
+}
+
+impl GroupSummary {
+    pub fn new<'a>(sensors: impl IntoIterator<Item = &'a CompositeSensor>) -> Self {
+        let mut summary = Self::default();
+        let mut temperatures = Vec::new();
+        for sensor in sensors {
+            if let Some(m) = sensor.motion.as_ref().filter(|m| m.enabled) {
+                summary.motion_sensors += 1;
+                if m.presence {
+                    summary.occupied += 1;
+                }
+            }
+            if let Some(t) = sensor.temperature.as_ref().filter(|t| t.enabled) {
+                temperatures.push(t.temperature);
+            }
+        }
+        if !temperatures.is_empty() {
+            summary.avg_temperature =
+                Some(temperatures.iter().sum::<f64>() / temperatures.len() as f64);
+        }
+        summary
+    }
+}
+
+/// Groups sensors by room, keeping their order within each room. Rooms are sorted by name, with
+/// sensors that aren't in a room last.
+pub fn group_by_room(sensors: &[CompositeSensor]) -> Vec<(Option<SensorGroup>, Vec<CompositeSensor>)> {
+    let mut groups: Vec<(Option<SensorGroup>, Vec<CompositeSensor>)> = Vec::new();
+    for sensor in sensors {
+        match groups.iter_mut().find(|(room, _)| *room == sensor.room) {
+            Some((_, members)) => members.push(sensor.clone()),
+            None => groups.push((sensor.room.clone(), vec![sensor.clone()])),
+        }
+    }
+    groups.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) => a.name.cmp(&b.name),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+    groups
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -45,10 +326,115 @@ pub struct CompositeSensor {
     pub motion: Option<MotionData>,
     pub temperature: Option<TemperatureData>,
     pub light: Option<LightData>,
+    /// The room the device is assigned to on the bridge
+    #[serde(default)]
+    pub room: Option<SensorGroup>,
+    /// Zones containing the device, one of its services, or its room
+    #[serde(default)]
+    pub zones: Vec<SensorGroup>,
+    #[serde(default)]
+    pub settings: SensorSettings,
 }
 
 impl CompositeSensor {
+    /// The device id followed by the ids of its sensor services
+    pub fn resource_ids(&self) -> impl Iterator<Item = &String> {
+        let services = [
+            self.motion.as_ref().map(|m| &m.id),
+            self.temperature.as_ref().map(|t| &t.id),
+            self.light.as_ref().map(|l| &l.id),
+        ];
+        std::iter::once(&self.device_id).chain(services.into_iter().flatten())
+    }
+
+    /// The card icon, from the settings or guessed from the name
+    pub fn icon(&self) -> &str {
+        if let Some(icon) = self.settings.icon.as_deref().filter(|i| !i.is_empty()) {
+            return icon;
+        }
+        let name = self.name.to_lowercase();
+        if self.is_outdoor {
+            "🌲"
+        } else if name.contains("garage") {
+            "🚗"
+        } else if name.contains("shed") {
+            "🛠️"
+        } else {
+            "🏠"
+        }
+    }
+
+    /// The time of the most recent report from any of the sensor's services
+    pub fn last_seen(&self) -> Option<DateTime<Utc>> {
+        [
+            self.motion.as_ref().map(|m| m.last_updated),
+            self.temperature.as_ref().map(|t| t.last_updated),
+            self.light.as_ref().map(|l| l.last_updated),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+    }
+
+    /// The shortest expected reporting interval of the sensor's enabled periodic metrics
+    pub fn expected_interval(&self) -> Option<Duration> {
+        [
+            self.temperature
+                .as_ref()
+                .filter(|t| t.enabled)
+                .map(|_| Duration::minutes(TEMPERATURE_REPORT_INTERVAL_MINUTES)),
+            self.light
+                .as_ref()
+                .filter(|l| l.enabled)
+                .map(|_| Duration::minutes(LIGHT_REPORT_INTERVAL_MINUTES)),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Judges health from the latest report of any metric, since a report from one service shows
+    /// the device is alive. Sensors without periodic metrics are always fresh.
+    pub fn health(&self, now: DateTime<Utc>) -> SensorHealth {
+        match (self.expected_interval(), self.last_seen()) {
+            (Some(interval), Some(last_seen)) => SensorHealth::from_age(now - last_seen, interval),
+            _ => SensorHealth::Fresh,
+        }
+    }
+
+    /// Orders sensors by display order, then outdoor sensors first, then by name
+    pub fn display_cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let order = |s: &Self| s.settings.display_order.unwrap_or(i32::MAX);
+        order(self)
+            .cmp(&order(other))
+            .then_with(|| other.is_outdoor.cmp(&self.is_outdoor))
+            .then_with(|| self.name.cmp(&other.name))
+    }
+
+    /// Applies a bridge event, correcting temperatures by the calibration offset
     pub fn apply_event(&mut self, event: &HueEvent) {
+        match event {
+            HueEvent::Temperature {
+                id,
+                owner_rid,
+                temperature,
+                changed,
+                enabled,
+            } if self.settings.temperature_offset != 0.0 => {
+                self.merge_event(&HueEvent::Temperature {
+                    id: id.clone(),
+                    owner_rid: owner_rid.clone(),
+                    temperature: temperature + self.settings.temperature_offset,
+                    changed: *changed,
+                    enabled: *enabled,
+                })
+            }
+            _ => self.merge_event(event),
+        }
+    }
+
+    /// Merges an event whose values are already calibrated, such as one built from a [`SensorUpdate`]
+    pub fn merge_event(&mut self, event: &HueEvent) {
         match event {
             HueEvent::Motion {
                 id,
@@ -63,13 +449,13 @@ impl CompositeSensor {
                     enabled: *enabled,
                     presence: *presence,
                     last_updated: *changed,
-                    history: Arc::new(Vec::new()),
+                    history: Arc::new(History::new(HistoryRetention::current().motion)),
                 });
 
                 motion.enabled = *enabled;
                 motion.presence = *presence;
                 motion.last_updated = *changed;
-                Self::update_history(Arc::make_mut(&mut motion.history), *changed, *presence);
+                Arc::make_mut(&mut motion.history).insert(*changed, *presence);
             }
             HueEvent::Temperature {
                 id,
@@ -84,13 +470,13 @@ impl CompositeSensor {
                     enabled: *enabled,
                     temperature: *temperature,
                     last_updated: *changed,
-                    history: Arc::new(Vec::new()),
+                    history: Arc::new(History::new(HistoryRetention::current().temperature)),
                 });
 
                 temp.enabled = *enabled;
                 temp.temperature = *temperature;
                 temp.last_updated = *changed;
-                Self::update_history(Arc::make_mut(&mut temp.history), *changed, *temperature);
+                Arc::make_mut(&mut temp.history).insert(*changed, *temperature);
             }
             HueEvent::LightLevel {
                 id,
@@ -99,19 +485,14 @@ impl CompositeSensor {
                 enabled,
                 ..
             } => {
-                let light = self.light.get_or_insert_with(|| LightData {
-                    id: id.clone(),
-                    id_v1: None,
-                    enabled: *enabled,
-                    light_level: *light_level,
-                    last_updated: *changed,
-                    history: Arc::new(Vec::new()),
+                let light = self.light.get_or_insert_with(|| {
+                    LightData::new(id.clone(), None, *enabled, *light_level, *changed)
                 });
 
                 light.enabled = *enabled;
-                light.light_level = *light_level;
+                light.set_light_level(*light_level);
                 light.last_updated = *changed;
-                Self::update_history(Arc::make_mut(&mut light.history), *changed, *light_level);
+                Arc::make_mut(&mut light.history).insert(*changed, *light_level);
             }
             HueEvent::Raw(_) => return,
         }
@@ -138,36 +519,4 @@ impl CompositeSensor {
                 .unwrap_or_default(),
         )
     }
-
-    pub fn update_history<T: PartialEq + Clone + std::fmt::Display>(
-        history: &mut Vec<(DateTime<Utc>, T)>,
-        time: DateTime<Utc>,
-        val: T,
-    ) {
-        if !history.iter().any(|(t, _)| *t == time) {
-            history.push((time, val));
-        }
-        history.sort_by_key(|(t, _)| *t);
-
-        let limit = Utc::now() - chrono::Duration::minutes(15);
-
-        // Find the index of the first point that is within the limit
-        let cutoff_index = history.iter().position(|(t, _)| *t >= limit);
-
-        if let Some(idx) = cutoff_index {
-            // If there are points older than the limit, we want to keep the one immediately preceding the cutoff
-            // as an anchor for the graph rendering (so we know the value at the start of the window).
-            // So we remove everything before (idx - 1).
-            if idx > 0 {
-                history.drain(0..(idx - 1));
-            }
-        } else {
-            // All points are older than the limit.
-            // Keep only the last one (most recent state).
-            if history.len() > 1 {
-                let keep = history.len() - 1;
-                history.drain(0..keep);
-            }
-        }
-    }
 }
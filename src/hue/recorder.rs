@@ -0,0 +1,186 @@
+use crate::hue::storage::{self, Reading, Storage};
+use std::collections::HashMap;
+use std::time::Duration;
+
+const DEFAULT_SNAPSHOT_MINUTES: u64 = 5;
+/// How far back `huebot backfill` looks when no number of days is given
+const DEFAULT_BACKFILL_DAYS: i64 = 3650;
+/// Days of history copied at a time by `huebot backfill`, bounding how much is held in memory
+const BACKFILL_WINDOW_DAYS: i64 = 30;
+
+/// Whether the recorder is turned on (`HISTORY_RECORDER=true`). Graphs read a service from the
+/// recorder's tables once anything is recorded for it, and until then by v1 id through the
+/// externally maintained `sensor_*` functions.
+pub fn enabled() -> bool {
+    std::env::var("HISTORY_RECORDER")
+        .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// How often the full sensor state is recorded (`HISTORY_SNAPSHOT_MINUTES`, default 5). Snapshots
+/// fill any gaps left by missed events; readings already recorded are skipped.
+fn snapshot_interval() -> Duration {
+    let minutes = super::env_usize("HISTORY_SNAPSHOT_MINUTES")
+        .map(|m| m as u64)
+        .filter(|m| *m > 0)
+        .unwrap_or(DEFAULT_SNAPSHOT_MINUTES);
+    Duration::from_secs(minutes * 60)
+}
+
+/// Starts the recorder on its own thread if it is enabled. It only subscribes to `EVENT_CHANNEL`;
+/// the bridge listener runs on the server's runtime, started when the server starts.
+pub fn spawn() {
+    if !enabled() {
+        return;
+    }
+    std::thread::spawn(|| {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(run());
+    });
+}
+
+async fn connect() -> Storage {
+    loop {
+        let result = match std::env::var("DATABASE_URL") {
+            Ok(url) => Storage::connect(&url, 2).await.map_err(|e| e.to_string()),
+            Err(_) => Err("DATABASE_URL must be set".to_string()),
+        };
+        match result {
+            Ok(storage) => return storage,
+            Err(e) => {
+                println!("History recorder unable to connect to database: {}. Retrying in 10s...", e);
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+        }
+    }
+}
+
+async fn run() {
+    let storage = connect().await;
+    if let Err(e) = storage.migrate().await {
+        println!("History recorder unable to migrate the database: {}", e);
+        return;
+    }
+    println!("History recorder started.");
+
+    let mut rx = super::EVENT_CHANNEL.subscribe();
+    let mut snapshot = tokio::time::interval(snapshot_interval());
+
+    loop {
+        tokio::select! {
+            received = rx.recv() => match received {
+                Ok(cached) => {
+                    if let Some(reading) = Reading::from_event(&cached.event) {
+                        write(&storage, &[reading]).await;
+                    }
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    println!("History recorder missed {} events; the next snapshot will fill the gap", skipped);
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            },
+            _ = snapshot.tick() => record_snapshot(&storage).await,
+        }
+    }
+}
+
+/// Records the current value of every sensor, straight from the bridge so readings are uncalibrated
+/// like those from events
+async fn record_snapshot(storage: &Storage) {
+    match super::get_hue_client().get_sensors(&HashMap::new()).await {
+        Ok(sensors) => {
+            let readings: Vec<Reading> = sensors.iter().flat_map(Reading::from_sensor).collect();
+            write(storage, &readings).await;
+
+            let v1_ids: Vec<_> = sensors.iter().flat_map(storage::v1_ids).collect();
+            if let Err(e) = storage.upsert_v1_ids(&v1_ids).await {
+                println!("History recorder unable to write v1 ids: {}", e);
+            }
+        }
+        Err(e) => println!("History recorder unable to snapshot sensors: {}", e),
+    }
+}
+
+async fn write(storage: &Storage, readings: &[Reading]) {
+    if let Err(e) = storage.insert_readings(readings).await {
+        println!("History recorder unable to write readings: {}", e);
+    }
+}
+
+/// Copies history logged by v1 sensor id (read through the `sensor_*` functions) into
+/// `huebot_readings`, where graphs read it by v2 resource id. The v1 to v2 mapping comes from the
+/// bridge and is saved to `huebot_v1_ids`. Readings already present are skipped, so this can be
+/// rerun. Returns the number of readings copied.
+pub async fn backfill(days: Option<i64>) -> Result<u64, String> {
+    let storage = storage::connect_from_env().await?;
+    if storage::auto_migrate() {
+        storage.migrate().await.map_err(|e| e.to_string())?;
+    }
+    let sensors = super::get_hue_client()
+        .get_sensors(&HashMap::new())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let end = chrono::Utc::now();
+    let from = end - chrono::Duration::days(days.unwrap_or(DEFAULT_BACKFILL_DAYS));
+    let mut copied = 0;
+
+    for sensor in &sensors {
+        storage
+            .upsert_v1_ids(&storage::v1_ids(sensor))
+            .await
+            .map_err(|e| e.to_string())?;
+
+        for (v1_id, resource_id, metric) in storage::v1_services(sensor) {
+            let mut window_end = end;
+            let mut sensor_copied = 0;
+            let mut earliest_copied = None;
+            while window_end > from {
+                let window_start = (window_end - chrono::Duration::days(BACKFILL_WINDOW_DAYS)).max(from);
+                let readings: Vec<Reading> = storage
+                    .read_v1_readings(v1_id, metric, window_start, window_end)
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .into_iter()
+                    .map(|(time, value)| Reading {
+                        resource_id: resource_id.clone(),
+                        device_id: sensor.device_id.clone(),
+                        metric,
+                        time,
+                        value,
+                    })
+                    .collect();
+                let window_copied = storage
+                    .insert_readings(&readings)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                // Windows run backwards, so the last with anything copied holds the earliest reading
+                if window_copied > 0 {
+                    earliest_copied = readings.iter().map(|r| r.time).min();
+                }
+                sensor_copied += window_copied;
+                window_end = window_start;
+            }
+            // Roll up the copied history before maintenance expires it. Rollups already summarising
+            // readings that have since been deleted are kept.
+            if let Some(earliest) = earliest_copied {
+                storage
+                    .rewind_rollups(&resource_id, earliest)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            println!(
+                "{} {} (v1 sensor {}): {} readings",
+                sensor.name,
+                metric.as_str(),
+                v1_id,
+                sensor_copied
+            );
+            copied += sensor_copied;
+        }
+    }
+    Ok(copied)
+}
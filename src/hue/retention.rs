@@ -0,0 +1,276 @@
+use crate::hue::aggregate::{aggregate, bucket_start, duty_cycle, Aggregate};
+use crate::hue::models::Metric;
+use crate::hue::storage::Storage;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+const DEFAULT_POLICY: &str = "raw:14d,5m:1y,1h:forever";
+const DEFAULT_MAINTENANCE_MINUTES: u64 = 60;
+/// Most buckets rolled up per query, bounding how many readings are held in memory at once
+const ROLLUP_WINDOW_BUCKETS: i32 = 288;
+
+/// The policy set by `HISTORY_RETENTION`, by default raw readings for 14 days, 5-minute rollups
+/// for a year and hourly rollups forever
+pub static POLICY: LazyLock<RetentionPolicy> = LazyLock::new(|| {
+    let spec = std::env::var("HISTORY_RETENTION").unwrap_or_else(|_| DEFAULT_POLICY.to_string());
+    RetentionPolicy::parse(&spec).unwrap_or_else(|e| {
+        println!("Invalid HISTORY_RETENTION ({}); using {}", e, DEFAULT_POLICY);
+        RetentionPolicy::parse(DEFAULT_POLICY).unwrap()
+    })
+});
+
+/// A resolution that readings are summarised at, and how long those summaries are kept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rollup {
+    pub resolution: Duration,
+    pub keep: Option<Duration>,
+}
+
+/// How long sensor history is kept at each resolution. `None` keeps it forever.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    pub raw: Option<Duration>,
+    /// From finest to coarsest
+    pub rollups: Vec<Rollup>,
+}
+
+impl RetentionPolicy {
+    /// Parses a comma-separated list of `<resolution>:<keep>` tiers, where the resolution is `raw`
+    /// or a duration and `keep` is a duration or `forever`, e.g. `raw:14d,5m:1y,1h:forever`.
+    /// Durations are a number followed by `s`, `m`, `h`, `d`, `w` or `y`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut policy = Self {
+            raw: None,
+            rollups: Vec::new(),
+        };
+        for tier in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let (resolution, keep) = tier
+                .split_once(':')
+                .ok_or_else(|| format!("expected <resolution>:<keep> in {:?}", tier))?;
+            let keep = match keep.trim() {
+                "forever" => None,
+                keep => Some(parse_duration(keep)?),
+            };
+            match resolution.trim() {
+                "raw" => policy.raw = keep,
+                resolution => policy.rollups.push(Rollup {
+                    resolution: parse_duration(resolution)?,
+                    keep,
+                }),
+            }
+        }
+        policy.rollups.sort_by_key(|r| r.resolution);
+        if let Some(r) = policy.rollups.iter().find(|r| r.resolution.num_seconds() < 1) {
+            return Err(format!("rollup resolution {} is under a second", r.resolution));
+        }
+        Ok(policy)
+    }
+
+    /// The rollup resolution to read a range starting at `start` from, or `None` to read raw
+    /// readings. Raw readings are used while they cover the range, then the finest rollup that
+    /// does, or failing that the one kept longest.
+    pub fn source_for(&self, start: DateTime<Utc>, now: DateTime<Utc>) -> Option<Duration> {
+        let covers = |keep: Option<Duration>| keep.map_or(true, |keep| start >= now - keep);
+        if covers(self.raw) || self.rollups.is_empty() {
+            return None;
+        }
+        self.rollups
+            .iter()
+            .find(|r| covers(r.keep))
+            .or_else(|| {
+                self.rollups
+                    .iter()
+                    .max_by_key(|r| r.keep.unwrap_or(Duration::MAX))
+            })
+            .map(|r| r.resolution)
+    }
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, unit) = s.split_at(split);
+    let n: i64 = n.parse().map_err(|_| format!("invalid duration {:?}", s))?;
+    match unit {
+        "s" => Ok(Duration::seconds(n)),
+        "m" => Ok(Duration::minutes(n)),
+        "h" => Ok(Duration::hours(n)),
+        "d" => Ok(Duration::days(n)),
+        "w" => Ok(Duration::weeks(n)),
+        "y" => Ok(Duration::days(n * 365)),
+        _ => Err(format!("invalid duration unit in {:?}", s)),
+    }
+}
+
+/// How often maintenance runs (`HISTORY_MAINTENANCE_MINUTES`, default 60)
+fn maintenance_interval() -> std::time::Duration {
+    let minutes = super::env_usize("HISTORY_MAINTENANCE_MINUTES")
+        .map(|m| m as u64)
+        .filter(|m| *m > 0)
+        .unwrap_or(DEFAULT_MAINTENANCE_MINUTES);
+    std::time::Duration::from_secs(minutes * 60)
+}
+
+/// Counts of the rows written and removed by one maintenance run
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MaintenanceReport {
+    pub rollups_written: usize,
+    pub readings_deleted: u64,
+    pub rollups_deleted: u64,
+}
+
+/// Starts the maintenance task on its own thread when a database is configured and the policy
+/// has anything to do
+pub fn spawn() {
+    if std::env::var("DATABASE_URL").is_err()
+        || (POLICY.raw.is_none() && POLICY.rollups.is_empty())
+    {
+        return;
+    }
+    std::thread::spawn(|| {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(run());
+    });
+}
+
+async fn run() {
+    let mut storage: Option<Storage> = None;
+    let mut interval = tokio::time::interval(maintenance_interval());
+    loop {
+        interval.tick().await;
+        if storage.is_none() {
+            match connect().await {
+                Ok(connected) => storage = Some(connected),
+                Err(e) => {
+                    println!("History maintenance unable to open the database: {}", e);
+                    continue;
+                }
+            }
+        }
+        let Some(storage) = &storage else { continue };
+        match maintain(storage, &POLICY, Utc::now()).await {
+            Ok(report) => println!(
+                "History maintenance: {} rollups written, {} readings and {} rollups deleted",
+                report.rollups_written, report.readings_deleted, report.rollups_deleted
+            ),
+            Err(e) => println!("History maintenance failed: {}", e),
+        }
+    }
+}
+
+async fn connect() -> Result<Storage, String> {
+    let storage = super::storage::connect_from_env().await?;
+    if super::storage::auto_migrate() {
+        storage.migrate().await.map_err(|e| e.to_string())?;
+    }
+    Ok(storage)
+}
+
+/// Rolls up every resource's readings to each of the policy's resolutions, then deletes readings
+/// and rollups older than the policy keeps them. Readings are only deleted once rolled up.
+pub async fn maintain(
+    storage: &Storage,
+    policy: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> Result<MaintenanceReport, sqlx::Error> {
+    let mut report = MaintenanceReport::default();
+    let raw_cutoff = policy.raw.map(|keep| now - keep);
+
+    for (resource_id, device_id, metric, earliest) in storage.reading_resources().await? {
+        let mut rolled_up_to: Option<DateTime<Utc>> = None;
+        for rollup in &policy.rollups {
+            let resolution = rollup.resolution;
+            let end = bucket_start(now, resolution);
+            let mut from = bucket_start(earliest, resolution);
+            // Watermarks rewound by a backfill or import may fall mid-bucket
+            if let Some(watermark) = storage.rollup_watermark(resolution, &resource_id).await? {
+                from = from.max(bucket_start(watermark, resolution));
+            }
+
+            while from < end {
+                let to = (from + resolution * ROLLUP_WINDOW_BUCKETS).min(end);
+                let rows =
+                    roll_up(storage, &resource_id, metric, resolution, from, to, raw_cutoff).await?;
+                storage
+                    .upsert_rollups(&resource_id, &device_id, metric, resolution, &rows)
+                    .await?;
+                storage
+                    .set_rollup_watermark(resolution, &resource_id, to)
+                    .await?;
+                report.rollups_written += rows.len();
+                from = to;
+            }
+            rolled_up_to = Some(rolled_up_to.map_or(end, |t| t.min(end)));
+        }
+
+        if let Some(keep) = policy.raw {
+            let cutoff = rolled_up_to.map_or(now - keep, |t| t.min(now - keep));
+            report.readings_deleted += storage
+                .delete_readings_before(&resource_id, cutoff)
+                .await?;
+        }
+    }
+
+    for rollup in &policy.rollups {
+        if let Some(keep) = rollup.keep {
+            report.rollups_deleted += storage
+                .delete_rollups_before(rollup.resolution, now - keep)
+                .await?;
+        }
+    }
+    Ok(report)
+}
+
+/// Summarises one resource's readings in `from..to`, which is aligned to `resolution`. Motion is
+/// summarised as the fraction of each bucket with motion detected.
+///
+/// Buckets starting before `raw_cutoff` may have had their readings deleted (a backfill or import
+/// rewinds the watermark into that span), so there only buckets that still hold readings and have
+/// no rollup yet are summarised. Rollups of deleted readings are never replaced.
+async fn roll_up(
+    storage: &Storage,
+    resource_id: &str,
+    metric: Metric,
+    resolution: Duration,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    raw_cutoff: Option<DateTime<Utc>>,
+) -> Result<Vec<Aggregate>, sqlx::Error> {
+    let last = to - Duration::milliseconds(1);
+    let series = storage.read_readings(resource_id, from, last).await?;
+    let mut rows = match metric {
+        Metric::Motion => duty_cycle(&series, from, to, resolution)
+            .into_iter()
+            .map(|(start, on)| Aggregate {
+                start,
+                min: on,
+                avg: on,
+                max: on,
+            })
+            .collect(),
+        Metric::Temperature | Metric::LightLevel => aggregate(&series, from, last, resolution),
+    };
+
+    if let Some(cutoff) = raw_cutoff.filter(|cutoff| from < *cutoff) {
+        let rolled_up: HashSet<DateTime<Utc>> = storage
+            .read_rollups(resource_id, resolution, from, last, resolution)
+            .await?
+            .into_iter()
+            .map(|a| a.start)
+            .collect();
+        // The reading before `from` only carries the state in, so it doesn't count
+        let with_readings: HashSet<DateTime<Utc>> = series
+            .iter()
+            .filter(|(t, _)| *t >= from)
+            .map(|(t, _)| bucket_start(*t, resolution))
+            .collect();
+        rows.retain(|a| {
+            a.start >= cutoff || (with_readings.contains(&a.start) && !rolled_up.contains(&a.start))
+        });
+    }
+    Ok(rows)
+}
@@ -0,0 +1,69 @@
+use crate::hue::models::SensorSettings;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// Per-device [`SensorSettings`], persisted as a single JSON file.
+///
+/// The file is small and rarely written, so it's rewritten in full on every change (via a temp file
+/// and an atomic rename, as for the event cache log).
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: RwLock<HashMap<String, SensorSettings>>,
+}
+
+impl SettingsStore {
+    /// Loads the store from `path`. A missing file is treated as empty; an unreadable one is
+    /// reported and left untouched until the next save.
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let settings = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                println!("Unable to parse sensor settings {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                println!("Unable to read sensor settings {}: {}", path.display(), e);
+                HashMap::new()
+            }
+        };
+        Self {
+            path,
+            settings: RwLock::new(settings),
+        }
+    }
+
+    pub fn get(&self, device_id: &str) -> SensorSettings {
+        self.settings
+            .read()
+            .unwrap()
+            .get(device_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn all(&self) -> HashMap<String, SensorSettings> {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Replaces a device's settings, removing the entry when they're back to the defaults
+    pub fn set(&self, device_id: &str, settings: SensorSettings) -> std::io::Result<()> {
+        let mut map = self.settings.write().unwrap();
+        if settings == SensorSettings::default() {
+            map.remove(device_id);
+        } else {
+            map.insert(device_id.to_string(), settings);
+        }
+
+        let tmp_path = self.path.with_extension("tmp");
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            tmp.write_all(serde_json::to_string_pretty(&*map)?.as_bytes())?;
+            tmp.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, &self.path)
+    }
+}
@@ -0,0 +1,260 @@
+use crate::hue::aggregate::{carry_forward, Aggregate};
+use crate::hue::export::ExportTimeZone;
+use crate::hue::models::{
+    light_level_to_lux, SensorHealth, LIGHT_REPORT_INTERVAL_MINUTES,
+    TEMPERATURE_REPORT_INTERVAL_MINUTES,
+};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Light at or above which a bucket counts as daylight, in lux
+pub const DAYLIGHT_LUX: f64 = 50.0;
+/// Local hours from which, and before which, a bucket is part of the night
+const NIGHT_FROM_HOUR: u32 = 18;
+const NIGHT_UNTIL_HOUR: u32 = 6;
+
+/// One sensor's summary of a local calendar day. Fields are `None` when the sensor has no such
+/// service or no history for the day.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyStats {
+    pub date: NaiveDate,
+    /// In °C
+    pub min_temperature: Option<f64>,
+    pub max_temperature: Option<f64>,
+    pub mean_temperature: Option<f64>,
+    pub daylight_hours: Option<f64>,
+    /// Times motion started. Counted from raw readings, so `None` for days they have expired for.
+    pub motion_events: Option<u32>,
+    pub occupied_minutes: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RecordKind {
+    /// The lowest night-time temperature of the latest winter (December to February), in °C
+    ColdestNight,
+    /// The highest temperature in °C
+    WarmestDay,
+    /// The most hours of daylight in a day
+    LongestDaylight,
+    /// The most minutes with motion detected in a day
+    MostActiveDay,
+    /// The longest unbroken run of motion, in minutes
+    LongestMotion,
+}
+
+/// A record and when it was set. `date` is the local day (or, for nights, the evening) it belongs to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatRecord {
+    pub kind: RecordKind,
+    pub date: NaiveDate,
+    pub time: DateTime<Utc>,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SensorStats {
+    pub name: String,
+    /// Oldest first
+    pub days: Vec<DailyStats>,
+    pub records: Vec<StatRecord>,
+    /// Motion events are counted from raw readings, which are only kept from this time
+    pub raw_since: DateTime<Utc>,
+}
+
+/// The history statistics are computed from, in buckets of `bucket`
+#[derive(Debug, Clone)]
+pub struct StatsInput {
+    /// Calibrated temperatures in °C
+    pub temperatures: Vec<Aggregate>,
+    /// Raw bridge light levels
+    pub light_levels: Vec<Aggregate>,
+    /// The fraction of each bucket with motion detected
+    pub occupancy: Vec<Aggregate>,
+    /// Raw motion readings since `raw_since`, 1 for motion and 0 for none
+    pub motions: Vec<(DateTime<Utc>, f64)>,
+    pub bucket: Duration,
+}
+
+/// The first day of the latest winter to have started by `today`
+pub fn winter_start(today: NaiveDate) -> NaiveDate {
+    let year = if today.month() == 12 {
+        today.year()
+    } else {
+        today.year() - 1
+    };
+    NaiveDate::from_ymd_opt(year, 12, 1).unwrap_or(today)
+}
+
+/// Summarises each local day from `first_day` to today, and finds the records set in that time
+/// (or, for the coldest night, in the latest winter)
+pub fn compute(
+    input: &StatsInput,
+    tz: &ExportTimeZone,
+    first_day: NaiveDate,
+    raw_since: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> (Vec<DailyStats>, Vec<StatRecord>) {
+    let date_of = |t: DateTime<Utc>| tz.localize(t).date_naive();
+    let today = date_of(now);
+    let bucket_hours = input.bucket.num_seconds() as f64 / 3600.0;
+    let raw_from_day = date_of(raw_since);
+    let runs = motion_runs(&input.motions, now);
+    // Readings hold until the next, so a bucket without any still has the last value, until the
+    // sensor has been quiet long enough to count as silent
+    let silent_after = |minutes| SensorHealth::silent_after(Duration::minutes(minutes));
+    let temperatures = carry_forward(
+        &input.temperatures,
+        now,
+        input.bucket,
+        silent_after(TEMPERATURE_REPORT_INTERVAL_MINUTES),
+    );
+    let light_levels = carry_forward(
+        &input.light_levels,
+        now,
+        input.bucket,
+        silent_after(LIGHT_REPORT_INTERVAL_MINUTES),
+    );
+
+    let mut days = Vec::new();
+    let mut day = first_day;
+    while day <= today {
+        let on_day = |a: &&Aggregate| date_of(a.start) == day;
+
+        let temperatures: Vec<&Aggregate> = temperatures.iter().filter(on_day).collect();
+        let (min_temperature, max_temperature, mean_temperature) = if temperatures.is_empty() {
+            (None, None, None)
+        } else {
+            (
+                temperatures.iter().map(|a| a.min).reduce(f64::min),
+                temperatures.iter().map(|a| a.max).reduce(f64::max),
+                Some(temperatures.iter().map(|a| a.avg).sum::<f64>() / temperatures.len() as f64),
+            )
+        };
+
+        let lights: Vec<&Aggregate> = light_levels.iter().filter(on_day).collect();
+        let daylight_hours = (!lights.is_empty()).then(|| {
+            let bright = lights
+                .iter()
+                .filter(|a| light_level_to_lux(a.avg.round() as i32) >= DAYLIGHT_LUX)
+                .count();
+            bright as f64 * bucket_hours
+        });
+
+        let occupancy: Vec<&Aggregate> = input.occupancy.iter().filter(on_day).collect();
+        let occupied_minutes = (!occupancy.is_empty())
+            .then(|| occupancy.iter().map(|a| a.avg).sum::<f64>() * bucket_hours * 60.0);
+        // Only days wholly after raw_since have every event
+        let motion_events = (!occupancy.is_empty() && day > raw_from_day).then(|| {
+            runs.iter()
+                .filter(|(start, _)| date_of(*start) == day)
+                .count() as u32
+        });
+
+        days.push(DailyStats {
+            date: day,
+            min_temperature,
+            max_temperature,
+            mean_temperature,
+            daylight_hours,
+            motion_events,
+            occupied_minutes,
+        });
+        day = match day.succ_opt() {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    let mut records = Vec::new();
+
+    // Nights run from the evening into the next morning and belong to the evening's date
+    let winter_from = winter_start(today);
+    let winter_until = NaiveDate::from_ymd_opt(winter_from.year() + 1, 3, 1).unwrap_or(today);
+    let coldest = temperatures
+        .iter()
+        .filter_map(|a| {
+            let local = tz.localize(a.start);
+            let night = match local.hour() {
+                h if h >= NIGHT_FROM_HOUR => local.date_naive(),
+                h if h < NIGHT_UNTIL_HOUR => local.date_naive().pred_opt()?,
+                _ => return None,
+            };
+            (night >= winter_from && night < winter_until).then_some((night, a))
+        })
+        .min_by(|(_, a), (_, b)| a.min.total_cmp(&b.min));
+    if let Some((date, a)) = coldest {
+        records.push(StatRecord {
+            kind: RecordKind::ColdestNight,
+            date,
+            time: a.start,
+            value: a.min,
+        });
+    }
+
+    let warmest = temperatures
+        .iter()
+        .filter(|a| date_of(a.start) >= first_day)
+        .max_by(|a, b| a.max.total_cmp(&b.max));
+    if let Some(a) = warmest {
+        records.push(StatRecord {
+            kind: RecordKind::WarmestDay,
+            date: date_of(a.start),
+            time: a.start,
+            value: a.max,
+        });
+    }
+
+    let best_day = |kind: RecordKind, value: fn(&DailyStats) -> Option<f64>| {
+        days.iter()
+            .filter_map(|d| Some((d.date, value(d)?)))
+            .filter(|(_, v)| *v > 0.0)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(date, value)| StatRecord {
+                kind,
+                date,
+                time: tz.midnight(date).unwrap_or(now),
+                value,
+            })
+    };
+    records.extend(best_day(RecordKind::LongestDaylight, |d| d.daylight_hours));
+    records.extend(best_day(RecordKind::MostActiveDay, |d| d.occupied_minutes));
+
+    let longest = runs
+        .into_iter()
+        .filter(|(start, _)| date_of(*start) >= first_day)
+        .max_by_key(|(_, length)| *length);
+    if let Some((start, length)) = longest {
+        records.push(StatRecord {
+            kind: RecordKind::LongestMotion,
+            date: date_of(start),
+            time: start,
+            value: length.num_seconds() as f64 / 60.0,
+        });
+    }
+
+    (days, records)
+}
+
+/// The start and length of each unbroken run of motion. Repeated readings of the same state (such
+/// as periodic snapshots) don't break a run, and a run still going lasts until `now`.
+pub fn motion_runs(
+    motions: &[(DateTime<Utc>, f64)],
+    now: DateTime<Utc>,
+) -> Vec<(DateTime<Utc>, Duration)> {
+    let mut runs = Vec::new();
+    let mut started: Option<DateTime<Utc>> = None;
+    for (t, v) in motions {
+        match (started, *v != 0.0) {
+            (None, true) => started = Some(*t),
+            (Some(start), false) => {
+                runs.push((start, *t - start));
+                started = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = started {
+        runs.push((start, now - start));
+    }
+    runs
+}
@@ -0,0 +1,743 @@
+use crate::hue::aggregate::Aggregate;
+use crate::hue::events::HueEvent;
+use crate::hue::models::{CompositeSensor, Metric};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use sqlx::{PgPool, SqlitePool};
+use std::str::FromStr;
+
+static POSTGRES_MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations/postgres");
+static SQLITE_MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// Rows per insert statement, keeping the bound parameters well within both backends' limits
+const INSERT_CHUNK: usize = 1000;
+
+/// One normalised sensor reading. Motion is stored as 0 or 1 and light as the raw bridge level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    pub resource_id: String,
+    pub device_id: String,
+    pub metric: Metric,
+    pub time: DateTime<Utc>,
+    pub value: f64,
+}
+
+impl Reading {
+    pub fn from_event(event: &HueEvent) -> Option<Self> {
+        let (id, owner_rid, metric, time, value) = match event {
+            HueEvent::Motion {
+                id,
+                owner_rid,
+                presence,
+                changed,
+                ..
+            } => (id, owner_rid, Metric::Motion, changed, *presence as i32 as f64),
+            HueEvent::Temperature {
+                id,
+                owner_rid,
+                temperature,
+                changed,
+                ..
+            } => (id, owner_rid, Metric::Temperature, changed, *temperature),
+            HueEvent::LightLevel {
+                id,
+                owner_rid,
+                light_level,
+                changed,
+                ..
+            } => (id, owner_rid, Metric::LightLevel, changed, *light_level as f64),
+            HueEvent::Raw(_) => return None,
+        };
+        Some(Self {
+            resource_id: id.clone(),
+            device_id: owner_rid.clone(),
+            metric,
+            time: *time,
+            value,
+        })
+    }
+
+    /// The latest reading of each of the sensor's metrics
+    pub fn from_sensor(sensor: &CompositeSensor) -> Vec<Self> {
+        let reading = |resource_id: &String, metric, time, value| Self {
+            resource_id: resource_id.clone(),
+            device_id: sensor.device_id.clone(),
+            metric,
+            time,
+            value,
+        };
+
+        let mut readings = Vec::new();
+        if let Some(m) = &sensor.motion {
+            readings.push(reading(&m.id, Metric::Motion, m.last_updated, m.presence as i32 as f64));
+        }
+        if let Some(t) = &sensor.temperature {
+            readings.push(reading(&t.id, Metric::Temperature, t.last_updated, t.temperature));
+        }
+        if let Some(l) = &sensor.light {
+            readings.push(reading(&l.id, Metric::LightLevel, l.last_updated, l.light_level as f64));
+        }
+        readings
+    }
+}
+
+/// The v1 sensor number, v2 resource id and metric of each of a sensor's services that has a v1 id
+pub fn v1_services(sensor: &CompositeSensor) -> Vec<(i32, String, Metric)> {
+    let services = [
+        sensor.motion.as_ref().map(|m| (&m.id_v1, &m.id, Metric::Motion)),
+        sensor.temperature.as_ref().map(|t| (&t.id_v1, &t.id, Metric::Temperature)),
+        sensor.light.as_ref().map(|l| (&l.id_v1, &l.id, Metric::LightLevel)),
+    ];
+    services
+        .into_iter()
+        .flatten()
+        .filter_map(|(id_v1, id, metric)| {
+            let v1 = id_v1.as_ref()?.split('/').last()?.parse().ok()?;
+            Some((v1, id.clone(), metric))
+        })
+        .collect()
+}
+
+/// The v1 sensor number and v2 resource id of each of a sensor's services that has a v1 id
+pub fn v1_ids(sensor: &CompositeSensor) -> Vec<(i32, String)> {
+    v1_services(sensor)
+        .into_iter()
+        .map(|(v1, id, _)| (v1, id))
+        .collect()
+}
+
+/// Whether migrations run when the database is first opened (`DATABASE_AUTO_MIGRATE`, default
+/// true). Turn this off to apply them only with `huebot migrate`.
+pub fn auto_migrate() -> bool {
+    std::env::var("DATABASE_AUTO_MIGRATE")
+        .map(|v| !matches!(v.trim().to_lowercase().as_str(), "0" | "false" | "no"))
+        .unwrap_or(true)
+}
+
+/// Connects to `DATABASE_URL`, for the command line tools
+pub async fn connect_from_env() -> Result<Storage, String> {
+    let url = std::env::var("DATABASE_URL").map_err(|_| "DATABASE_URL must be set".to_string())?;
+    Storage::connect(&url, 5).await.map_err(|e| e.to_string())
+}
+
+/// Connects to `DATABASE_URL` and applies any pending migrations, for `huebot migrate`
+pub async fn migrate_from_env() -> Result<(), String> {
+    let storage = connect_from_env().await?;
+    storage.migrate().await.map_err(|e| e.to_string())
+}
+
+fn to_millis(t: DateTime<Utc>) -> i64 {
+    t.timestamp_millis()
+}
+
+fn from_millis(ms: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(ms).unwrap_or_default()
+}
+
+/// The history database: Postgres for shared servers, or a SQLite file for single-box installs.
+/// The backend is chosen from the scheme of the database URL.
+#[derive(Debug, Clone)]
+pub enum Storage {
+    Postgres(PgPool),
+    Sqlite(SqlitePool),
+}
+
+impl Storage {
+    /// Opens a `postgres://` or `sqlite:` URL. SQLite files are created if missing and use WAL mode,
+    /// so the recorder and the web server can use them at the same time.
+    pub async fn connect(url: &str, max_connections: u32) -> Result<Self, sqlx::Error> {
+        if url.starts_with("sqlite:") {
+            let options = SqliteConnectOptions::from_str(url)?
+                .create_if_missing(true)
+                .journal_mode(SqliteJournalMode::Wal);
+            // Every connection to an in-memory database gets its own, so share one
+            let max_connections = if url.contains(":memory:") { 1 } else { max_connections };
+            let pool = SqlitePoolOptions::new()
+                .max_connections(max_connections)
+                .connect_with(options)
+                .await?;
+            Ok(Self::Sqlite(pool))
+        } else {
+            let pool = PgPoolOptions::new()
+                .max_connections(max_connections)
+                .connect(url)
+                .await?;
+            Ok(Self::Postgres(pool))
+        }
+    }
+
+    /// Applies any pending migrations
+    pub async fn migrate(&self) -> Result<(), sqlx::migrate::MigrateError> {
+        match self {
+            Self::Postgres(pool) => POSTGRES_MIGRATOR.run(pool).await,
+            Self::Sqlite(pool) => SQLITE_MIGRATOR.run(pool).await,
+        }
+    }
+
+    /// Inserts readings, skipping any already recorded for the same resource and time. Returns
+    /// the number of new rows.
+    pub async fn insert_readings(&self, readings: &[Reading]) -> Result<u64, sqlx::Error> {
+        let insert = "insert into huebot_readings (resource_id, device_id, metric, recorded_at, value) ";
+
+        let mut inserted = 0;
+        for chunk in readings.chunks(INSERT_CHUNK) {
+            inserted += match self {
+                Self::Postgres(pool) => {
+                    let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new(insert);
+                    query.push_values(chunk, |mut row, r| {
+                        row.push_bind(&r.resource_id)
+                            .push_bind(&r.device_id)
+                            .push_bind(r.metric.as_str())
+                            .push_bind(r.time)
+                            .push_bind(r.value);
+                    });
+                    query.push(" on conflict do nothing");
+                    query.build().execute(pool).await?.rows_affected()
+                }
+                Self::Sqlite(pool) => {
+                    let mut query = sqlx::QueryBuilder::<sqlx::Sqlite>::new(insert);
+                    query.push_values(chunk, |mut row, r| {
+                        row.push_bind(&r.resource_id)
+                            .push_bind(&r.device_id)
+                            .push_bind(r.metric.as_str())
+                            .push_bind(to_millis(r.time))
+                            .push_bind(r.value);
+                    });
+                    query.push(" on conflict do nothing");
+                    query.build().execute(pool).await?.rows_affected()
+                }
+            };
+        }
+        Ok(inserted)
+    }
+
+    /// Records which v2 resource each v1 sensor number belongs to
+    pub async fn upsert_v1_ids(&self, ids: &[(i32, String)]) -> Result<(), sqlx::Error> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let insert = "insert into huebot_v1_ids (v1_id, resource_id) ";
+        let conflict = " on conflict (resource_id) do update set v1_id = excluded.v1_id";
+
+        match self {
+            Self::Postgres(pool) => {
+                let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new(insert);
+                query.push_values(ids, |mut row, (v1_id, resource_id)| {
+                    row.push_bind(*v1_id).push_bind(resource_id);
+                });
+                query.push(conflict);
+                query.build().execute(pool).await?;
+            }
+            Self::Sqlite(pool) => {
+                let mut query = sqlx::QueryBuilder::<sqlx::Sqlite>::new(insert);
+                query.push_values(ids, |mut row, (v1_id, resource_id)| {
+                    row.push_bind(*v1_id).push_bind(resource_id);
+                });
+                query.push(conflict);
+                query.build().execute(pool).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether anything has been recorded for a resource
+    pub async fn has_readings(&self, resource_id: &str) -> Result<bool, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => {
+                sqlx::query_scalar("select exists (select 1 from huebot_readings where resource_id = $1)")
+                    .bind(resource_id)
+                    .fetch_one(pool)
+                    .await
+            }
+            Self::Sqlite(pool) => {
+                sqlx::query_scalar("select exists (select 1 from huebot_readings where resource_id = ?1)")
+                    .bind(resource_id)
+                    .fetch_one(pool)
+                    .await
+            }
+        }
+    }
+
+    /// Readings for a resource between `start` and `end`, preceded by the last one before `start`
+    /// so the value at the start of the range is known
+    pub async fn read_readings(
+        &self,
+        resource_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<(DateTime<Utc>, f64)>, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => {
+                sqlx::query_as(
+                    r#"
+                    (select recorded_at, value from huebot_readings
+                        where resource_id = $1 and recorded_at < $2
+                        order by recorded_at desc limit 1)
+                    union all
+                    (select recorded_at, value from huebot_readings
+                        where resource_id = $1 and recorded_at >= $2 and recorded_at <= $3)
+                    order by recorded_at
+                    "#,
+                )
+                .bind(resource_id)
+                .bind(start)
+                .bind(end)
+                .fetch_all(pool)
+                .await
+            }
+            Self::Sqlite(pool) => {
+                let rows: Vec<(i64, f64)> = sqlx::query_as(
+                    r#"
+                    select recorded_at, value from (
+                        select * from (
+                            select recorded_at, value from huebot_readings
+                            where resource_id = ?1 and recorded_at < ?2
+                            order by recorded_at desc limit 1
+                        )
+                        union all
+                        select recorded_at, value from huebot_readings
+                        where resource_id = ?1 and recorded_at >= ?2 and recorded_at <= ?3
+                    )
+                    order by recorded_at
+                    "#,
+                )
+                .bind(resource_id)
+                .bind(to_millis(start))
+                .bind(to_millis(end))
+                .fetch_all(pool)
+                .await?;
+                Ok(rows.into_iter().map(|(t, v)| (from_millis(t), v)).collect())
+            }
+        }
+    }
+
+    /// Readings for a resource between `start` and `end`, summarised into epoch-aligned buckets
+    pub async fn read_aggregates(
+        &self,
+        resource_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        bucket: Duration,
+    ) -> Result<Vec<Aggregate>, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => {
+                let rows: Vec<(DateTime<Utc>, f64, f64, f64)> = sqlx::query_as(
+                    r#"
+                    select to_timestamp(floor(extract(epoch from recorded_at)::float8 / $4::float8) * $4::float8) as bucket,
+                        min(value), avg(value), max(value)
+                    from huebot_readings
+                    where resource_id = $1 and recorded_at >= $2 and recorded_at <= $3
+                    group by bucket
+                    order by bucket
+                    "#,
+                )
+                .bind(resource_id)
+                .bind(start)
+                .bind(end)
+                .bind(bucket.num_milliseconds() as f64 / 1000.0)
+                .fetch_all(pool)
+                .await?;
+                Ok(rows
+                    .into_iter()
+                    .map(|(start, min, avg, max)| Aggregate { start, min, avg, max })
+                    .collect())
+            }
+            Self::Sqlite(pool) => {
+                let rows: Vec<(i64, f64, f64, f64)> = sqlx::query_as(
+                    r#"
+                    select (recorded_at / ?4) * ?4 as bucket, min(value), avg(value), max(value)
+                    from huebot_readings
+                    where resource_id = ?1 and recorded_at >= ?2 and recorded_at <= ?3
+                    group by bucket
+                    order by bucket
+                    "#,
+                )
+                .bind(resource_id)
+                .bind(to_millis(start))
+                .bind(to_millis(end))
+                .bind(bucket.num_milliseconds().max(1))
+                .fetch_all(pool)
+                .await?;
+                Ok(rows
+                    .into_iter()
+                    .map(|(t, min, avg, max)| Aggregate {
+                        start: from_millis(t),
+                        min,
+                        avg,
+                        max,
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// Readings for a v1 sensor number between `start` and `end`, for copying history logged by v1
+    /// id into `huebot_readings`. On Postgres this goes through the `sensor_*` functions, which may
+    /// be backed by an external logger's tables.
+    pub async fn read_v1_readings(
+        &self,
+        v1_id: i32,
+        metric: Metric,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<(DateTime<Utc>, f64)>, sqlx::Error> {
+        use sqlx::Row;
+
+        match self {
+            Self::Postgres(pool) => {
+                let sql = match metric {
+                    Metric::Motion => "select creationtime, motion from sensor_motion($1, $2, $3)",
+                    Metric::Temperature => {
+                        "select creationtime, temperature from sensor_temperature($1, $2, $3)"
+                    }
+                    Metric::LightLevel => {
+                        "select creationtime, light_level from sensor_light_level($1, $2, $3)"
+                    }
+                };
+                let rows = sqlx::query(sql)
+                    .bind(v1_id)
+                    .bind(start)
+                    .bind(end)
+                    .fetch_all(pool)
+                    .await?;
+
+                Ok(rows
+                    .iter()
+                    .filter_map(|row| {
+                        let creationtime: chrono::NaiveDateTime = row.get(0);
+                        let value = match metric {
+                            Metric::Motion => row.get::<Option<bool>, _>(1).map(|v| v as i32 as f64),
+                            Metric::Temperature => row.get::<Option<f32>, _>(1).map(|v| v as f64),
+                            Metric::LightLevel => row.get::<Option<i32>, _>(1).map(|v| v as f64),
+                        }?;
+                        Some((DateTime::from_naive_utc_and_offset(creationtime, Utc), value))
+                    })
+                    .collect())
+            }
+            Self::Sqlite(pool) => {
+                let rows: Vec<(i64, f64)> = sqlx::query_as(
+                    r#"
+                    select r.recorded_at, r.value from huebot_readings r
+                    join huebot_v1_ids i on i.resource_id = r.resource_id
+                    where i.v1_id = ?1 and r.metric = ?2
+                        and r.recorded_at between ?3 and ?4
+                    order by r.recorded_at
+                    "#,
+                )
+                .bind(v1_id)
+                .bind(metric.as_str())
+                .bind(to_millis(start))
+                .bind(to_millis(end))
+                .fetch_all(pool)
+                .await?;
+                Ok(rows.into_iter().map(|(t, v)| (from_millis(t), v)).collect())
+            }
+        }
+    }
+
+    /// Every resource with readings, with its device, metric and earliest reading
+    pub async fn reading_resources(
+        &self,
+    ) -> Result<Vec<(String, String, Metric, DateTime<Utc>)>, sqlx::Error> {
+        let sql = r#"
+            select resource_id, min(device_id), min(metric), min(recorded_at)
+            from huebot_readings
+            group by resource_id
+            order by resource_id
+            "#;
+        let rows: Vec<(String, String, String, DateTime<Utc>)> = match self {
+            Self::Postgres(pool) => sqlx::query_as(sql).fetch_all(pool).await?,
+            Self::Sqlite(pool) => {
+                let rows: Vec<(String, String, String, i64)> =
+                    sqlx::query_as(sql).fetch_all(pool).await?;
+                rows.into_iter()
+                    .map(|(id, device, metric, t)| (id, device, metric, from_millis(t)))
+                    .collect()
+            }
+        };
+        Ok(rows
+            .into_iter()
+            .filter_map(|(id, device, metric, t)| Some((id, device, Metric::parse(&metric)?, t)))
+            .collect())
+    }
+
+    /// Deletes a resource's readings before `cutoff`, except the last one, which still gives the
+    /// value at the start of the remaining history. Returns the number of rows deleted.
+    pub async fn delete_readings_before(
+        &self,
+        resource_id: &str,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => Ok(sqlx::query(
+                r#"
+                delete from huebot_readings
+                where resource_id = $1 and recorded_at < (
+                    select max(recorded_at) from huebot_readings
+                    where resource_id = $1 and recorded_at < $2
+                )
+                "#,
+            )
+            .bind(resource_id)
+            .bind(cutoff)
+            .execute(pool)
+            .await?
+            .rows_affected()),
+            Self::Sqlite(pool) => Ok(sqlx::query(
+                r#"
+                delete from huebot_readings
+                where resource_id = ?1 and recorded_at < (
+                    select max(recorded_at) from huebot_readings
+                    where resource_id = ?1 and recorded_at < ?2
+                )
+                "#,
+            )
+            .bind(resource_id)
+            .bind(to_millis(cutoff))
+            .execute(pool)
+            .await?
+            .rows_affected()),
+        }
+    }
+
+    /// Writes rollups at `resolution`, replacing any already stored for the same buckets
+    pub async fn upsert_rollups(
+        &self,
+        resource_id: &str,
+        device_id: &str,
+        metric: Metric,
+        resolution: Duration,
+        rollups: &[Aggregate],
+    ) -> Result<(), sqlx::Error> {
+        let insert = "insert into huebot_rollups \
+            (resolution, resource_id, device_id, metric, bucket_start, min_value, avg_value, max_value) ";
+        let conflict = " on conflict (resolution, resource_id, bucket_start) do update set \
+            min_value = excluded.min_value, avg_value = excluded.avg_value, max_value = excluded.max_value";
+        let resolution = resolution.num_seconds() as i32;
+
+        for chunk in rollups.chunks(INSERT_CHUNK) {
+            match self {
+                Self::Postgres(pool) => {
+                    let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new(insert);
+                    query.push_values(chunk, |mut row, a| {
+                        row.push_bind(resolution)
+                            .push_bind(resource_id)
+                            .push_bind(device_id)
+                            .push_bind(metric.as_str())
+                            .push_bind(a.start)
+                            .push_bind(a.min)
+                            .push_bind(a.avg)
+                            .push_bind(a.max);
+                    });
+                    query.push(conflict);
+                    query.build().execute(pool).await?;
+                }
+                Self::Sqlite(pool) => {
+                    let mut query = sqlx::QueryBuilder::<sqlx::Sqlite>::new(insert);
+                    query.push_values(chunk, |mut row, a| {
+                        row.push_bind(resolution)
+                            .push_bind(resource_id)
+                            .push_bind(device_id)
+                            .push_bind(metric.as_str())
+                            .push_bind(to_millis(a.start))
+                            .push_bind(a.min)
+                            .push_bind(a.avg)
+                            .push_bind(a.max);
+                    });
+                    query.push(conflict);
+                    query.build().execute(pool).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A resource's rollups at `resolution` between `start` and `end`, combined into buckets of
+    /// `bucket` (a multiple of the resolution)
+    pub async fn read_rollups(
+        &self,
+        resource_id: &str,
+        resolution: Duration,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        bucket: Duration,
+    ) -> Result<Vec<Aggregate>, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => {
+                let rows: Vec<(DateTime<Utc>, f64, f64, f64)> = sqlx::query_as(
+                    r#"
+                    select to_timestamp(floor(extract(epoch from bucket_start)::float8 / $5::float8) * $5::float8) as bucket,
+                        min(min_value), avg(avg_value), max(max_value)
+                    from huebot_rollups
+                    where resolution = $1 and resource_id = $2 and bucket_start >= $3 and bucket_start <= $4
+                    group by bucket
+                    order by bucket
+                    "#,
+                )
+                .bind(resolution.num_seconds() as i32)
+                .bind(resource_id)
+                .bind(start)
+                .bind(end)
+                .bind(bucket.num_milliseconds() as f64 / 1000.0)
+                .fetch_all(pool)
+                .await?;
+                Ok(rows
+                    .into_iter()
+                    .map(|(start, min, avg, max)| Aggregate { start, min, avg, max })
+                    .collect())
+            }
+            Self::Sqlite(pool) => {
+                let rows: Vec<(i64, f64, f64, f64)> = sqlx::query_as(
+                    r#"
+                    select (bucket_start / ?5) * ?5 as bucket,
+                        min(min_value), avg(avg_value), max(max_value)
+                    from huebot_rollups
+                    where resolution = ?1 and resource_id = ?2 and bucket_start >= ?3 and bucket_start <= ?4
+                    group by bucket
+                    order by bucket
+                    "#,
+                )
+                .bind(resolution.num_seconds() as i32)
+                .bind(resource_id)
+                .bind(to_millis(start))
+                .bind(to_millis(end))
+                .bind(bucket.num_milliseconds().max(1))
+                .fetch_all(pool)
+                .await?;
+                Ok(rows
+                    .into_iter()
+                    .map(|(t, min, avg, max)| Aggregate {
+                        start: from_millis(t),
+                        min,
+                        avg,
+                        max,
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// Deletes rollups at `resolution` for buckets before `cutoff`. Returns the number deleted.
+    pub async fn delete_rollups_before(
+        &self,
+        resolution: Duration,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, sqlx::Error> {
+        let resolution = resolution.num_seconds() as i32;
+        match self {
+            Self::Postgres(pool) => Ok(sqlx::query(
+                "delete from huebot_rollups where resolution = $1 and bucket_start < $2",
+            )
+            .bind(resolution)
+            .bind(cutoff)
+            .execute(pool)
+            .await?
+            .rows_affected()),
+            Self::Sqlite(pool) => Ok(sqlx::query(
+                "delete from huebot_rollups where resolution = ?1 and bucket_start < ?2",
+            )
+            .bind(resolution)
+            .bind(to_millis(cutoff))
+            .execute(pool)
+            .await?
+            .rows_affected()),
+        }
+    }
+
+    /// The time up to which a resource has been rolled up at `resolution`
+    pub async fn rollup_watermark(
+        &self,
+        resolution: Duration,
+        resource_id: &str,
+    ) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let resolution = resolution.num_seconds() as i32;
+        match self {
+            Self::Postgres(pool) => {
+                sqlx::query_scalar(
+                    "select rolled_up_to from huebot_rollup_watermarks where resolution = $1 and resource_id = $2",
+                )
+                .bind(resolution)
+                .bind(resource_id)
+                .fetch_optional(pool)
+                .await
+            }
+            Self::Sqlite(pool) => {
+                let t: Option<i64> = sqlx::query_scalar(
+                    "select rolled_up_to from huebot_rollup_watermarks where resolution = ?1 and resource_id = ?2",
+                )
+                .bind(resolution)
+                .bind(resource_id)
+                .fetch_optional(pool)
+                .await?;
+                Ok(t.map(from_millis))
+            }
+        }
+    }
+
+    pub async fn set_rollup_watermark(
+        &self,
+        resolution: Duration,
+        resource_id: &str,
+        rolled_up_to: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        let resolution = resolution.num_seconds() as i32;
+        match self {
+            Self::Postgres(pool) => {
+                sqlx::query(
+                    r#"
+                    insert into huebot_rollup_watermarks (resolution, resource_id, rolled_up_to)
+                    values ($1, $2, $3)
+                    on conflict (resolution, resource_id) do update set rolled_up_to = excluded.rolled_up_to
+                    "#,
+                )
+                .bind(resolution)
+                .bind(resource_id)
+                .bind(rolled_up_to)
+                .execute(pool)
+                .await?;
+            }
+            Self::Sqlite(pool) => {
+                sqlx::query(
+                    r#"
+                    insert into huebot_rollup_watermarks (resolution, resource_id, rolled_up_to)
+                    values (?1, ?2, ?3)
+                    on conflict (resolution, resource_id) do update set rolled_up_to = excluded.rolled_up_to
+                    "#,
+                )
+                .bind(resolution)
+                .bind(resource_id)
+                .bind(to_millis(rolled_up_to))
+                .execute(pool)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves a resource's rollup watermarks back to `t`, so readings added from then on (by a
+    /// backfill or import) are rolled up again
+    pub async fn rewind_rollups(&self, resource_id: &str, t: DateTime<Utc>) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => {
+                sqlx::query(
+                    "update huebot_rollup_watermarks set rolled_up_to = $2 where resource_id = $1 and rolled_up_to > $2",
+                )
+                .bind(resource_id)
+                .bind(t)
+                .execute(pool)
+                .await?;
+            }
+            Self::Sqlite(pool) => {
+                sqlx::query(
+                    "update huebot_rollup_watermarks set rolled_up_to = ?2 where resource_id = ?1 and rolled_up_to > ?2",
+                )
+                .bind(resource_id)
+                .bind(to_millis(t))
+                .execute(pool)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,446 @@
+use crate::hue::events::{EventCursor, EventFilter, HueEvent};
+use crate::hue::models::{CompositeSensor, SensorUpdate};
+use dioxus::prelude::*;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// State of the tab's connection to the `hue_events` and `sensor_updates` streams
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Reconnecting,
+    /// The last connection attempt failed; a retry is pending
+    Error(String),
+    /// The page is hidden (or nothing is subscribed), so no stream is open
+    Idle,
+}
+
+impl ConnectionState {
+    /// The state of two streams shown as one: the more troubled of the two, or whichever is open
+    fn combine(a: Self, b: Self) -> Self {
+        let rank = |s: &Self| match s {
+            Self::Error(_) => 4,
+            Self::Reconnecting => 3,
+            Self::Connecting => 2,
+            Self::Connected => 1,
+            Self::Idle => 0,
+        };
+        if rank(&b) > rank(&a) {
+            b
+        } else {
+            a
+        }
+    }
+}
+
+struct Subscriber {
+    filter: EventFilter,
+    /// Resource ids to check locally, from [`EventFilter::members`]. Until the sensor list arrives,
+    /// only the filter's device ids are known.
+    members: Option<HashSet<String>>,
+    on_event: Box<dyn FnMut(HueEvent)>,
+    on_resync: Box<dyn FnMut()>,
+}
+
+struct UpdateSubscriber {
+    on_update: Box<dyn FnMut(SensorUpdate)>,
+    on_resync: Box<dyn FnMut()>,
+}
+
+#[derive(Default)]
+pub(super) struct Hub {
+    subscribers: HashMap<u64, Subscriber>,
+    update_subscribers: HashMap<u64, UpdateSubscriber>,
+    next_id: u64,
+    last_cursor: Option<EventCursor>,
+}
+
+impl Hub {
+    pub(super) fn subscribe(
+        &mut self,
+        filter: EventFilter,
+        on_event: impl FnMut(HueEvent) + 'static,
+        on_resync: impl FnMut() + 'static,
+    ) -> u64 {
+        let members = filter.members(&[]);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscribers.insert(
+            id,
+            Subscriber {
+                filter,
+                members,
+                on_event: Box::new(on_event),
+                on_resync: Box::new(on_resync),
+            },
+        );
+        id
+    }
+
+    /// Resolves a subscriber's rooms now that the sensors in them are known
+    pub(super) fn resolve_members(&mut self, id: u64, sensors: &[CompositeSensor]) {
+        if let Some(sub) = self.subscribers.get_mut(&id) {
+            sub.members = sub.filter.members(sensors);
+        }
+    }
+
+    pub(super) fn dispatch(&mut self, event: &HueEvent) {
+        for sub in self.subscribers.values_mut() {
+            if sub.filter.matches(event, sub.members.as_ref()) {
+                (sub.on_event)(event.clone());
+            }
+        }
+    }
+
+    fn resync(&mut self) {
+        for sub in self.subscribers.values_mut() {
+            (sub.on_resync)();
+        }
+    }
+
+    fn dispatch_update(&mut self, update: &SensorUpdate) {
+        for sub in self.update_subscribers.values_mut() {
+            (sub.on_update)(update.clone());
+        }
+    }
+
+    fn resync_updates(&mut self) {
+        for sub in self.update_subscribers.values_mut() {
+            (sub.on_resync)();
+        }
+    }
+}
+
+/// The tab-wide event subscription, provided as context by [`use_event_subscriptions_provider`]
+#[derive(Clone)]
+pub struct EventSubscriptions {
+    hub: Rc<RefCell<Hub>>,
+    /// The `hue_events` stream's state
+    state: Signal<ConnectionState>,
+    /// The `sensor_updates` stream's state
+    updates_state: Signal<ConnectionState>,
+    /// Both streams' state
+    combined_state: Memo<ConnectionState>,
+    /// Bumped whenever the set of subscribers changes so the stream reconnects with the new filter
+    version: Signal<u64>,
+    /// The number of sensor update subscribers; the diff stream is only open while there are some
+    update_subscribers: Signal<usize>,
+}
+
+impl EventSubscriptions {
+    fn subscribe(
+        &self,
+        filter: EventFilter,
+        on_event: impl FnMut(HueEvent) + 'static,
+        on_resync: impl FnMut() + 'static,
+    ) -> u64 {
+        self.hub.borrow_mut().subscribe(filter, on_event, on_resync)
+    }
+
+    fn unsubscribe(&self, id: u64) {
+        self.hub.borrow_mut().subscribers.remove(&id);
+        self.subscribers_changed();
+    }
+
+    fn subscribers_changed(&self) {
+        let mut version = self.version;
+        *version.write() += 1;
+    }
+
+    fn subscribe_updates(
+        &self,
+        on_update: impl FnMut(SensorUpdate) + 'static,
+        on_resync: impl FnMut() + 'static,
+    ) -> u64 {
+        let mut hub = self.hub.borrow_mut();
+        let id = hub.next_id;
+        hub.next_id += 1;
+        hub.update_subscribers.insert(
+            id,
+            UpdateSubscriber {
+                on_update: Box::new(on_update),
+                on_resync: Box::new(on_resync),
+            },
+        );
+        id
+    }
+
+    fn unsubscribe_updates(&self, id: u64) {
+        self.hub.borrow_mut().update_subscribers.remove(&id);
+        self.update_subscribers_changed();
+    }
+
+    fn update_subscribers_changed(&self) {
+        let mut count = self.update_subscribers;
+        count.set(self.hub.borrow().update_subscribers.len());
+    }
+
+    /// Has a subscriber that joined an already open diff stream refetch, since it missed the
+    /// stream's opening resync
+    fn resync_late_subscriber(&self, id: u64) {
+        if *self.updates_state.peek() != ConnectionState::Connected {
+            return;
+        }
+        if let Some(sub) = self.hub.borrow_mut().update_subscribers.get_mut(&id) {
+            (sub.on_resync)();
+        }
+    }
+
+    fn filter(&self) -> Option<EventFilter> {
+        let hub = self.hub.borrow();
+        if hub.subscribers.is_empty() {
+            return None;
+        }
+        Some(EventFilter::union(hub.subscribers.values().map(|s| &s.filter)))
+    }
+}
+
+/// Feeds streamed chunks through a line buffer, since chunks may split or merge lines, and calls
+/// `on_message` for every line that parses
+#[cfg(feature = "web")]
+async fn read_messages<M: serde::de::DeserializeOwned>(
+    stream: &mut dioxus::fullstack::TextStream,
+    mut on_message: impl FnMut(M),
+) {
+    use futures::StreamExt;
+
+    let mut buffer = String::new();
+    while let Some(Ok(chunk)) = stream.next().await {
+        buffer.push_str(&chunk);
+        while let Some(end) = buffer.find('\n') {
+            let line: String = buffer.drain(..=end).collect();
+            if let Ok(msg) = serde_json::from_str::<M>(&line) {
+                on_message(msg);
+            }
+        }
+    }
+}
+
+fn page_visible() -> bool {
+    #[cfg(feature = "web")]
+    {
+        web_sys::window()
+            .and_then(|w| w.document())
+            .map(|d| !d.hidden())
+            .unwrap_or(true)
+    }
+    #[cfg(not(feature = "web"))]
+    {
+        true
+    }
+}
+
+/// Opens one `hue_events` stream for the whole tab and fans events out to every
+/// [`use_hue_event_handler`], and likewise one `sensor_updates` stream for every
+/// [`use_sensor_updates`]. The streams pause while the page is hidden, and the event stream resumes
+/// from the last received cursor on reconnect.
+pub fn use_event_subscriptions_provider() -> EventSubscriptions {
+    let mut is_visible = use_signal(page_visible);
+    let state = use_signal(|| ConnectionState::Connecting);
+    let updates_state = use_signal(|| ConnectionState::Idle);
+    let combined_state = use_memo(move || ConnectionState::combine(state(), updates_state()));
+    let subscriptions = use_context_provider(|| EventSubscriptions {
+        hub: Rc::new(RefCell::new(Hub::default())),
+        state,
+        updates_state,
+        combined_state,
+        version: Signal::new(0),
+        update_subscribers: Signal::new(0),
+    });
+
+    let _listener = use_hook(|| {
+        #[cfg(feature = "web")]
+        {
+            let document = web_sys::window().unwrap().document().unwrap();
+            Rc::new(gloo_events::EventListener::new(
+                &document,
+                "visibilitychange",
+                move |_| is_visible.set(page_visible()),
+            ))
+        }
+        #[cfg(not(feature = "web"))]
+        {
+            Rc::new(())
+        }
+    });
+
+    let subs = subscriptions.clone();
+    use_resource(move || {
+        let subs = subs.clone();
+        let visible = is_visible();
+        // Reconnect with the combined filter whenever subscribers come and go
+        let _ = subs.version.read();
+
+        async move {
+            #[cfg(feature = "web")]
+            {
+                use crate::hue::events::StreamMessage;
+
+                let mut state = subs.state;
+                let Some(filter) = subs.filter().filter(|_| visible) else {
+                    state.set(ConnectionState::Idle);
+                    return;
+                };
+
+                if subs.hub.borrow().last_cursor.is_some() {
+                    state.set(ConnectionState::Reconnecting);
+                }
+
+                loop {
+                    let since = subs.hub.borrow().last_cursor;
+                    match crate::hue::hue_events(false, since, filter.clone()).await {
+                        Ok(mut stream) => {
+                            state.set(ConnectionState::Connected);
+                            read_messages(&mut stream, |msg: StreamMessage| {
+                                let mut hub = subs.hub.borrow_mut();
+                                match msg {
+                                    StreamMessage::Event(cached) => {
+                                        hub.last_cursor = Some(cached.cursor());
+                                        hub.dispatch(&cached.event);
+                                    }
+                                    StreamMessage::Cursor(cursor) => hub.last_cursor = Some(cursor),
+                                    StreamMessage::Lagged { .. } | StreamMessage::ResyncRequired => {
+                                        hub.resync()
+                                    }
+                                }
+                            })
+                            .await;
+                            state.set(ConnectionState::Reconnecting);
+                        }
+                        Err(e) => {
+                            state.set(ConnectionState::Error(e.to_string()));
+                        }
+                    }
+                    gloo_timers::future::TimeoutFuture::new(1000).await;
+
+                    // Re-check visibility before looping
+                    if !page_visible() {
+                        state.set(ConnectionState::Idle);
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    // Only reconnect the diff stream when it gains its first subscriber or loses its last
+    let update_subscribers = subscriptions.update_subscribers;
+    let wanted = use_memo(move || update_subscribers() > 0);
+    let subs = subscriptions.clone();
+    use_resource(move || {
+        let subs = subs.clone();
+        let open = is_visible() && wanted();
+
+        async move {
+            #[cfg(feature = "web")]
+            {
+                use crate::hue::events::SensorStreamMessage;
+
+                let mut state = subs.updates_state;
+                if !open {
+                    state.set(ConnectionState::Idle);
+                    return;
+                }
+                state.set(ConnectionState::Connecting);
+
+                loop {
+                    match crate::hue::sensor_updates().await {
+                        Ok(mut stream) => {
+                            state.set(ConnectionState::Connected);
+                            read_messages(&mut stream, |msg: SensorStreamMessage| {
+                                let mut hub = subs.hub.borrow_mut();
+                                match msg {
+                                    SensorStreamMessage::Update(update) => hub.dispatch_update(&update),
+                                    SensorStreamMessage::Resync => hub.resync_updates(),
+                                }
+                            })
+                            .await;
+                            state.set(ConnectionState::Reconnecting);
+                        }
+                        Err(e) => {
+                            state.set(ConnectionState::Error(e.to_string()));
+                        }
+                    }
+                    gloo_timers::future::TimeoutFuture::new(1000).await;
+
+                    if !page_visible() {
+                        state.set(ConnectionState::Idle);
+                        break;
+                    }
+                }
+            }
+            #[cfg(not(feature = "web"))]
+            let _ = (subs, open);
+        }
+    });
+
+    subscriptions
+}
+
+/// The connection state of the tab's shared streams
+pub fn use_connection_state() -> Memo<ConnectionState> {
+    use_context::<EventSubscriptions>().combined_state
+}
+
+/// Receives events passing `filter` from the tab's shared event stream for as long as the
+/// component is mounted. `on_resync` is called when events were dropped (the stream lagged, or
+/// missed events could not be replayed), so the caller should refetch its state.
+pub fn use_hue_event_handler(
+    filter: EventFilter,
+    on_event: impl FnMut(HueEvent) + 'static,
+    on_resync: impl FnMut() + 'static,
+) {
+    let subscriptions = use_context::<EventSubscriptions>();
+    let has_rooms = !filter.rooms.is_empty();
+    let id = use_hook({
+        let subscriptions = subscriptions.clone();
+        move || subscriptions.subscribe(filter, on_event, on_resync)
+    });
+
+    // Rooms are resolved from the sensors' room and zone ids
+    use_hook({
+        let hub = subscriptions.hub.clone();
+        move || {
+            if has_rooms {
+                spawn(async move {
+                    if let Ok(sensors) = crate::hue::get_sensors().await {
+                        hub.borrow_mut().resolve_members(id, &sensors);
+                    }
+                });
+            }
+        }
+    });
+
+    // Signal the change after mount rather than writing to the provider's state during render
+    use_effect({
+        let subscriptions = subscriptions.clone();
+        move || subscriptions.subscribers_changed()
+    });
+    use_drop(move || subscriptions.unsubscribe(id));
+}
+
+/// Receives the server's merged sensor-state diffs from the tab's shared `sensor_updates` stream
+/// for as long as the component is mounted. `on_resync` is called when the stream (re)connects,
+/// when this component joins a stream already open, and whenever updates were dropped, so the
+/// caller should refetch the full sensor list.
+pub fn use_sensor_updates(
+    on_update: impl FnMut(SensorUpdate) + 'static,
+    on_resync: impl FnMut() + 'static,
+) {
+    let subscriptions = use_context::<EventSubscriptions>();
+    let id = use_hook({
+        let subscriptions = subscriptions.clone();
+        move || subscriptions.subscribe_updates(on_update, on_resync)
+    });
+
+    use_effect({
+        let subscriptions = subscriptions.clone();
+        move || {
+            subscriptions.update_subscribers_changed();
+            subscriptions.resync_late_subscriber(id);
+        }
+    });
+    use_drop(move || subscriptions.unsubscribe_updates(id));
+}
@@ -167,3 +167,1036 @@ async fn test_typescript_configure_sensor() {
         println!("No motion sensors found to test configure_sensor");
     }
 }
+
+#[test]
+fn test_light_level_to_lux() {
+    use super::client::{light_level_to_lux, LightData};
+
+    assert_eq!(light_level_to_lux(0), 0.0);
+    assert!((light_level_to_lux(1) - 1.0).abs() < 1e-9);
+    assert!((light_level_to_lux(10001) - 10.0).abs() < 1e-9);
+    assert!((light_level_to_lux(40001) - 10000.0).abs() < 1e-6);
+
+    let dark = LightData::new("l".to_string(), None, true, 12000, chrono::Utc::now());
+    assert!(dark.dark && !dark.daylight);
+    let daylight = LightData::new("l".to_string(), None, true, 25000, chrono::Utc::now());
+    assert!(!daylight.dark && daylight.daylight);
+}
+
+#[allow(dead_code)]
+fn test_sensor(device_id: &str, motion_id: &str) -> super::client::CompositeSensor {
+    use super::client::{CompositeSensor, MotionData};
+
+    let now = chrono::Utc::now();
+    CompositeSensor {
+        device_id: device_id.to_string(),
+        name: device_id.to_string(),
+        is_outdoor: false,
+        enabled: true,
+        motion: Some(MotionData {
+            id: motion_id.to_string(),
+            id_v1: None,
+            enabled: true,
+            presence: false,
+            last_updated: now,
+            history: std::sync::Arc::new(super::history::History::with_sample(
+                chrono::Duration::minutes(15),
+                now,
+                false,
+            )),
+        }),
+        temperature: None,
+        light: None,
+        room: None,
+        zones: Vec::new(),
+        settings: Default::default(),
+    }
+}
+
+#[test]
+fn test_sensor_index_routing() {
+    use super::client::HueEvent;
+    use super::index::SensorIndex;
+
+    let mut sensors = vec![test_sensor("dev-a", "motion-a"), test_sensor("dev-b", "motion-b")];
+    let mut index = SensorIndex::new(&sensors);
+
+    let motion = |id: &str, owner_rid: &str| HueEvent::Motion {
+        id: id.to_string(),
+        owner_rid: owner_rid.to_string(),
+        presence: true,
+        changed: chrono::Utc::now(),
+        enabled: true,
+    };
+
+    // Owner match
+    assert_eq!(index.lookup(&motion("unknown", "dev-b")), Some(1));
+    // Resource match when the owner is missing
+    assert_eq!(index.lookup(&motion("motion-a", "")), Some(0));
+    // No match
+    assert_eq!(index.lookup(&motion("unknown", "dev-c")), None);
+
+    // Applying registers services introduced by the event
+    let temperature = HueEvent::Temperature {
+        id: "temp-a".to_string(),
+        owner_rid: "dev-a".to_string(),
+        temperature: 20.5,
+        changed: chrono::Utc::now(),
+        enabled: true,
+    };
+    assert_eq!(index.apply(&mut sensors, &temperature), Some(0));
+    assert_eq!(sensors[0].temperature.as_ref().map(|t| t.temperature), Some(20.5));
+    assert!(sensors[1].temperature.is_none());
+    assert_eq!(
+        index.lookup(&HueEvent::Raw(serde_json::json!({ "id": "temp-a" }))),
+        Some(0)
+    );
+}
+
+#[test]
+fn test_history_ordering_and_anchor() {
+    use super::history::History;
+    use chrono::{Duration, Utc};
+
+    let now = Utc::now();
+    let mut history = History::new(Duration::minutes(10));
+
+    history.insert(now - Duration::minutes(30), 1);
+    history.insert(now - Duration::minutes(20), 2);
+    history.insert(now - Duration::minutes(2), 4);
+    // Late sample inside the window, plus a duplicate timestamp that must be ignored
+    history.insert(now - Duration::minutes(5), 3);
+    history.insert(now - Duration::minutes(5), 99);
+
+    let values: Vec<i32> = history.iter().map(|(_, v)| *v).collect();
+    // The newest sample before the cutoff is kept as the anchor
+    assert_eq!(values, vec![2, 3, 4]);
+
+    // When everything has expired only the most recent sample survives
+    history.prune(now + Duration::hours(1));
+    assert_eq!(history.len(), 1);
+    assert_eq!(history.last().map(|(_, v)| *v), Some(4));
+}
+
+#[test]
+fn test_event_cache_log_reload() {
+    use super::eventcache::EventCache;
+    use std::io::Write;
+
+    let path = std::env::temp_dir().join(format!("huebot-events-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let add = |cache: &EventCache, raw: &str| {
+        let v: serde_json::Value = serde_json::from_str(raw).unwrap();
+        cache.add(super::client::HueEvent::from_json(&v).unwrap(), raw.to_string())
+    };
+
+    {
+        let cache = EventCache::with_log(30, &path);
+        add(&cache, r#"{"id":"a"}"#);
+        add(&cache, r#"{"id":"b"}"#);
+    }
+
+    // Simulate a crash part-way through writing a line
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(br#"{"ts":"2024-01-01T00:00"#).unwrap();
+    drop(file);
+
+    let cache = EventCache::with_log(30, &path);
+    let reloaded = cache.get_all();
+    assert_eq!(
+        reloaded.iter().map(|e| e.raw.as_str()).collect::<Vec<_>>(),
+        vec![r#"{"id":"a"}"#, r#"{"id":"b"}"#]
+    );
+    assert_eq!(reloaded.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![1, 2]);
+    // Numbering continues after the reloaded events
+    assert_eq!(add(&cache, r#"{"id":"c"}"#).seq, 3);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_event_cache_query() {
+    use super::client::HueEvent;
+    use super::eventcache::EventCache;
+    use super::events::EventQuery;
+
+    let cache = EventCache::new(30);
+    let motion = |id: &str, owner_rid: &str| HueEvent::Motion {
+        id: id.to_string(),
+        owner_rid: owner_rid.to_string(),
+        presence: true,
+        changed: chrono::Utc::now(),
+        enabled: true,
+    };
+    cache.add(motion("m1", "dev-a"), String::new());
+    cache.add(motion("m2", "dev-b"), String::new());
+    cache.add(motion("m1", "dev-a"), String::new());
+    cache.add(
+        HueEvent::Raw(serde_json::json!({ "id": "b1", "type": "button" })),
+        String::new(),
+    );
+
+    let by_owner = cache.query(&EventQuery {
+        owner_rid: Some("dev-a".to_string()),
+        ..Default::default()
+    });
+    assert_eq!(by_owner.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![1, 3]);
+
+    let buttons = cache.query(&EventQuery {
+        event_type: Some("button".to_string()),
+        ..Default::default()
+    });
+    assert_eq!(buttons.len(), 1);
+
+    let latest = cache.query(&EventQuery {
+        limit: Some(2),
+        ..Default::default()
+    });
+    assert_eq!(latest.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![3, 4]);
+
+    let future = cache.query(&EventQuery {
+        since: Some(chrono::Utc::now() + chrono::Duration::minutes(1)),
+        ..Default::default()
+    });
+    assert!(future.is_empty());
+}
+
+#[test]
+fn test_event_cache_replay_cursor() {
+    use super::client::HueEvent;
+    use super::eventcache::EventCache;
+    use super::events::EventCursor;
+
+    let cache = EventCache::new(30);
+    for id in ["a", "b", "c"] {
+        cache.add(HueEvent::Raw(serde_json::json!({ "id": id })), String::new());
+    }
+
+    let latest = cache.replay(None, false).latest;
+    let at = |seq| EventCursor { seq, ..latest };
+
+    let replay = cache.replay(Some(at(1)), false);
+    assert!(!replay.resync_required);
+    assert_eq!(replay.events.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![2, 3]);
+    assert_eq!(replay.latest, at(3));
+    assert!(replay.events.iter().all(|e| e.epoch == latest.epoch));
+
+    // Up to date
+    assert!(cache.replay(Some(at(3)), false).events.is_empty());
+    // A cursor ahead of the cache
+    assert!(cache.replay(Some(at(10)), false).resync_required);
+    // No cursor replays everything only when asked
+    assert_eq!(cache.replay(None, true).events.len(), 3);
+    assert!(cache.replay(None, false).events.is_empty());
+}
+
+#[test]
+fn test_event_cache_replay_after_restart() {
+    use super::client::HueEvent;
+    use super::eventcache::EventCache;
+    use super::events::EventCursor;
+
+    let add = |cache: &EventCache| {
+        cache.add(HueEvent::Raw(serde_json::json!({ "id": "a" })), String::new())
+    };
+
+    let before = EventCache::new(30);
+    add(&before);
+    let cursor = add(&before).cursor();
+
+    // Without a persistent cache, numbering starts again and soon passes the old cursor
+    let after = EventCache::new(30);
+    for _ in 0..5 {
+        add(&after);
+    }
+    let replay = after.replay(Some(cursor), false);
+    assert!(replay.resync_required);
+    assert!(replay.events.is_empty());
+    assert_ne!(replay.latest.epoch, cursor.epoch);
+
+    // The new run's own cursors still resume
+    let resumed = after.replay(Some(EventCursor { seq: 2, ..replay.latest }), false);
+    assert!(!resumed.resync_required);
+    assert_eq!(resumed.events.len(), 3);
+}
+
+#[tokio::test]
+async fn test_live_events_lagged() {
+    use super::client::HueEvent;
+    use super::eventcache::EventCache;
+    use super::events::{EventFilter, StreamMessage};
+    use futures::StreamExt;
+
+    let cache = EventCache::new(30);
+    let event = || cache.add(HueEvent::Raw(serde_json::json!({ "id": "a" })), String::new());
+    let next = |line: Option<String>| serde_json::from_str::<StreamMessage>(&line.unwrap()).unwrap();
+
+    // Overfilling the channel drops the oldest events, and the client is told how many
+    let (tx, rx) = tokio::sync::broadcast::channel(4);
+    for _ in 0..10 {
+        tx.send(event()).unwrap();
+    }
+    let mut stream = Box::pin(super::live_events(rx, 0, EventFilter::default(), None, None));
+    assert_eq!(next(stream.next().await), StreamMessage::Lagged { skipped: 6 });
+    assert!(matches!(next(stream.next().await), StreamMessage::Event(e) if e.seq == 7));
+
+    // A client further behind than max_pending skips to the live events
+    let (tx, rx) = tokio::sync::broadcast::channel(16);
+    for _ in 0..5 {
+        tx.send(event()).unwrap();
+    }
+    let mut stream = Box::pin(super::live_events(rx, 0, EventFilter::default(), None, Some(2)));
+    assert_eq!(next(stream.next().await), StreamMessage::Lagged { skipped: 5 });
+    let live = event();
+    tx.send(live.clone()).unwrap();
+    assert!(matches!(next(stream.next().await), StreamMessage::Event(e) if e.seq == live.seq));
+}
+
+#[test]
+fn test_event_filter() {
+    use super::client::HueEvent;
+    use super::events::EventFilter;
+
+    let motion = HueEvent::Motion {
+        id: "m1".to_string(),
+        owner_rid: "dev-a".to_string(),
+        presence: true,
+        changed: chrono::Utc::now(),
+        enabled: true,
+    };
+    let button = HueEvent::Raw(serde_json::json!({
+        "id": "b1",
+        "type": "button",
+        "owner": { "rid": "dev-b" }
+    }));
+
+    let all = EventFilter::default();
+    assert!(all.matches(&motion, None) && all.matches(&button, None));
+
+    let sensors = EventFilter::resource_types(&["motion", "temperature", "light_level"]);
+    assert!(sensors.matches(&motion, None));
+    assert!(!sensors.matches(&button, None));
+
+    let members: std::collections::HashSet<String> = ["dev-b".to_string()].into();
+    assert!(!all.matches(&motion, Some(&members)));
+    assert!(all.matches(&button, Some(&members)));
+}
+
+#[test]
+fn test_event_filter_union() {
+    use super::events::EventFilter;
+
+    let sensors = EventFilter::resource_types(&["motion", "temperature"]);
+    let lights = EventFilter {
+        resource_types: vec!["light_level".to_string(), "motion".to_string()],
+        device_ids: vec!["dev-a".to_string()],
+        rooms: Vec::new(),
+    };
+
+    let combined = EventFilter::union([&sensors, &lights]);
+    assert_eq!(combined.resource_types, vec!["motion", "temperature", "light_level"]);
+    // `sensors` covers every device, so the union does too
+    assert!(combined.device_ids.is_empty());
+
+    let everything = EventFilter::union([&sensors, &EventFilter::default()]);
+    assert_eq!(everything, EventFilter::default());
+}
+
+#[test]
+fn test_room_subscriber_receives_only_its_devices() {
+    use super::client::{HueEvent, SensorGroup};
+    use super::events::EventFilter;
+    use super::subscription::Hub;
+
+    let kitchen = SensorGroup { id: "room-kitchen".to_string(), name: "Kitchen".to_string() };
+    let mut sensors = vec![test_sensor("dev-a", "motion-a"), test_sensor("dev-b", "motion-b")];
+    sensors[0].room = Some(kitchen.clone());
+
+    let received = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    let mut hub = Hub::default();
+    let filter = EventFilter { rooms: vec![kitchen.id], ..Default::default() };
+    let id = hub.subscribe(
+        filter,
+        {
+            let received = received.clone();
+            move |event: HueEvent| received.borrow_mut().push(event.resource_id().map(str::to_string))
+        },
+        || {},
+    );
+
+    let motion = |id: &str, owner: &str| HueEvent::Motion {
+        id: id.to_string(),
+        owner_rid: owner.to_string(),
+        presence: true,
+        changed: chrono::Utc::now(),
+        enabled: true,
+    };
+
+    // Nothing is delivered before the room is resolved
+    hub.dispatch(&motion("motion-a", "dev-a"));
+    assert!(received.borrow().is_empty());
+
+    hub.resolve_members(id, &sensors);
+    hub.dispatch(&motion("motion-a", "dev-a"));
+    hub.dispatch(&motion("motion-b", "dev-b"));
+    assert_eq!(*received.borrow(), vec![Some("motion-a".to_string())]);
+}
+
+#[test]
+fn test_sensor_update_mirrors_server_cache() {
+    use super::client::HueEvent;
+    use super::index::SensorIndex;
+    use super::models::{SensorMetric, SensorUpdate};
+
+    let mut server = vec![test_sensor("dev-a", "motion-a")];
+    // Calibration is applied once on the server, not again by the client
+    server[0].settings.temperature_offset = -1.0;
+    let mut client = server.clone();
+    let mut server_index = SensorIndex::new(&server);
+    let mut client_index = SensorIndex::new(&client);
+
+    let now = chrono::Utc::now();
+    let events = [
+        HueEvent::Motion {
+            id: "motion-a".to_string(),
+            owner_rid: "dev-a".to_string(),
+            presence: true,
+            changed: now,
+            enabled: true,
+        },
+        // A service the sensor didn't have yet
+        HueEvent::Temperature {
+            id: "temp-a".to_string(),
+            owner_rid: "dev-a".to_string(),
+            temperature: 19.5,
+            changed: now,
+            enabled: true,
+        },
+    ];
+
+    for event in &events {
+        let i = server_index.apply(&mut server, event).unwrap();
+        let update = SensorUpdate::from_applied(&server[i], event).unwrap();
+        assert_eq!(update.device_id, "dev-a");
+        client_index.apply_update(&mut client, &update);
+    }
+
+    let update = SensorUpdate::from_applied(&server[0], &events[1]).unwrap();
+    assert_eq!(update.metric, SensorMetric::Temperature(18.5));
+    assert_eq!(update.resource_id, "temp-a");
+    assert_eq!(client, server);
+}
+
+#[test]
+fn test_group_by_room() {
+    use super::models::{group_by_room, GroupSummary, SensorGroup};
+
+    let kitchen = SensorGroup {
+        id: "room-k".to_string(),
+        name: "Kitchen".to_string(),
+    };
+    let hall = SensorGroup {
+        id: "room-h".to_string(),
+        name: "Hall".to_string(),
+    };
+
+    let mut a = test_sensor("dev-a", "motion-a");
+    a.room = Some(kitchen.clone());
+    a.motion.as_mut().unwrap().presence = true;
+    let mut b = test_sensor("dev-b", "motion-b");
+    b.room = Some(hall.clone());
+    let c = test_sensor("dev-c", "motion-c");
+    let mut d = test_sensor("dev-d", "motion-d");
+    d.room = Some(kitchen.clone());
+
+    let groups = group_by_room(&[a, b, c, d]);
+    let rooms: Vec<_> = groups.iter().map(|(room, _)| room.clone()).collect();
+    assert_eq!(rooms, vec![Some(hall), Some(kitchen), None]);
+
+    let kitchen_ids: Vec<_> = groups[1].1.iter().map(|s| s.device_id.as_str()).collect();
+    assert_eq!(kitchen_ids, vec!["dev-a", "dev-d"]);
+
+    let summary = GroupSummary::new(&groups[1].1);
+    assert_eq!(summary.occupied, 1);
+    assert_eq!(summary.motion_sensors, 2);
+    assert_eq!(summary.avg_temperature, None);
+}
+
+#[test]
+fn test_sensor_settings_store() {
+    use super::models::SensorSettings;
+    use super::settings::SettingsStore;
+
+    let path = std::env::temp_dir().join(format!("huebot-settings-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let settings = SensorSettings {
+        is_outdoor: Some(true),
+        icon: Some("🐔".to_string()),
+        display_order: Some(1),
+        tags: vec!["coop".to_string()],
+        temperature_offset: 0.5,
+    };
+    SettingsStore::open(&path).set("dev-a", settings.clone()).unwrap();
+
+    let store = SettingsStore::open(&path);
+    assert_eq!(store.get("dev-a"), settings);
+    assert_eq!(store.get("dev-b"), SensorSettings::default());
+
+    // Resetting to the defaults drops the entry
+    store.set("dev-a", SensorSettings::default()).unwrap();
+    assert!(SettingsStore::open(&path).all().is_empty());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_sensor_health() {
+    use super::client::{SensorHealth, TemperatureData};
+    use chrono::Duration;
+
+    let now = chrono::Utc::now();
+    let mut sensor = test_sensor("dev-a", "motion-a");
+    sensor.motion.as_mut().unwrap().last_updated = now - Duration::days(1);
+
+    // Motion alone only reports on change, so it is never judged
+    assert_eq!(sensor.health(now), SensorHealth::Fresh);
+
+    let temperature_at = |t| TemperatureData {
+        id: "temp-a".to_string(),
+        id_v1: None,
+        enabled: true,
+        temperature: 20.0,
+        last_updated: t,
+        history: Default::default(),
+    };
+
+    sensor.temperature = Some(temperature_at(now - Duration::minutes(10)));
+    assert_eq!(sensor.health(now), SensorHealth::Fresh);
+
+    sensor.temperature = Some(temperature_at(now - Duration::minutes(45)));
+    assert_eq!(sensor.health(now), SensorHealth::Stale);
+
+    sensor.temperature = Some(temperature_at(now - Duration::hours(3)));
+    assert_eq!(sensor.health(now), SensorHealth::Silent);
+
+    // Any recent report shows the device is still alive
+    sensor.motion.as_mut().unwrap().last_updated = now - Duration::minutes(1);
+    assert_eq!(sensor.health(now), SensorHealth::Fresh);
+}
+
+#[test]
+fn test_readings_from_events_and_sensors() {
+    use super::client::{HueEvent, LightData, Metric};
+    use super::storage::Reading;
+
+    let now = chrono::Utc::now();
+    let event = HueEvent::Motion {
+        id: "motion-a".to_string(),
+        owner_rid: "dev-a".to_string(),
+        presence: true,
+        changed: now,
+        enabled: true,
+    };
+    let reading = Reading::from_event(&event).unwrap();
+    assert_eq!(reading.metric, Metric::Motion);
+    assert_eq!(reading.device_id, "dev-a");
+    assert_eq!(reading.value, 1.0);
+    assert!(Reading::from_event(&HueEvent::Raw(serde_json::json!({}))).is_none());
+
+    let mut sensor = test_sensor("dev-a", "motion-a");
+    sensor.light = Some(LightData::new("light-a".to_string(), None, true, 20000, now));
+    let readings = Reading::from_sensor(&sensor);
+    let metrics: Vec<_> = readings.iter().map(|r| r.metric).collect();
+    assert_eq!(metrics, vec![Metric::Motion, Metric::LightLevel]);
+    assert_eq!(readings[1].value, 20000.0);
+}
+
+#[test]
+fn test_aggregate_and_duty_cycle() {
+    use super::aggregate::{aggregate, bucket_size, duty_cycle, GraphRange};
+    use chrono::{Duration, TimeZone, Utc};
+
+    let start = Utc.with_ymd_and_hms(2026, 10, 18, 0, 0, 0).unwrap();
+    let end = start + Duration::hours(2);
+    let at = |minutes: i64| start + Duration::minutes(minutes);
+    assert_eq!(bucket_size(start, end, 2), Duration::hours(1));
+
+    let series = [(at(10), 18.0), (at(50), 20.0), (at(70), 21.0), (at(150), 30.0)];
+    let buckets = aggregate(&series, start, end, Duration::hours(1));
+    assert_eq!(buckets.len(), 2);
+    assert_eq!((buckets[0].start, buckets[0].min, buckets[0].avg, buckets[0].max), (start, 18.0, 19.0, 20.0));
+    assert_eq!((buckets[1].start, buckets[1].avg), (at(60), 21.0));
+
+    // Motion from before the range until 0:30, then again 1:45 to the end
+    let motion = [(at(-5), 1.0), (at(30), 0.0), (at(105), 1.0)];
+    let cycle = duty_cycle(&motion, start, end, Duration::hours(1));
+    assert_eq!(cycle, vec![(start, 0.5), (at(60), 0.25)]);
+
+    for range in GraphRange::PRESETS.into_iter().chain([GraphRange::Custom { start, end }]) {
+        assert_eq!(range.to_string().parse::<GraphRange>(), Ok(range));
+    }
+    assert_eq!("".parse::<GraphRange>(), Ok(GraphRange::Day));
+    assert!(format!("{}..{}", end.timestamp(), start.timestamp()).parse::<GraphRange>().is_err());
+}
+
+#[test]
+fn test_retention_policy() {
+    use super::aggregate::{bucket_start, rollup_split};
+    use super::retention::{RetentionPolicy, Rollup};
+    use chrono::{Duration, Utc};
+
+    let policy = RetentionPolicy::parse("1h:forever, raw:14d, 5m:1y").unwrap();
+    assert_eq!(policy.raw, Some(Duration::days(14)));
+    assert_eq!(
+        policy.rollups,
+        vec![
+            Rollup { resolution: Duration::minutes(5), keep: Some(Duration::days(365)) },
+            Rollup { resolution: Duration::hours(1), keep: None },
+        ]
+    );
+    assert!(RetentionPolicy::parse("raw").is_err());
+    assert!(RetentionPolicy::parse("5x:1d").is_err());
+
+    // Each range is read from the finest history that still covers its start
+    let now = Utc::now();
+    assert_eq!(policy.source_for(now - Duration::days(7), now), None);
+    assert_eq!(policy.source_for(now - Duration::days(30), now), Some(Duration::minutes(5)));
+    assert_eq!(policy.source_for(now - Duration::days(800), now), Some(Duration::hours(1)));
+
+    // Reads switch from rollups to raw readings at a bucket boundary, so no bucket mixes the two
+    let bucket = Duration::hours(6);
+    let start = now - Duration::days(30);
+    let watermark = bucket_start(now, bucket) - Duration::hours(2);
+    let split = rollup_split(Some(watermark), start, now, bucket);
+    assert_eq!(split, bucket_start(watermark, bucket));
+    assert!(split <= watermark && watermark - split < bucket);
+    assert_eq!(rollup_split(None, start, now, bucket), start);
+    assert_eq!(rollup_split(Some(start - Duration::days(1)), start, now, bucket), start);
+}
+
+#[test]
+fn test_memory_graph_data() {
+    use super::aggregate::GraphRange;
+    use super::client::TemperatureData;
+    use super::history::History;
+    use chrono::{Duration, Utc};
+
+    let now = Utc::now();
+    let mut history = History::new(Duration::hours(6));
+    history.insert(now - Duration::hours(3), 18.0);
+    history.insert(now - Duration::minutes(30), 19.0);
+    let mut sensor = test_sensor("dev-a", "motion-a");
+    sensor.motion = None;
+    sensor.temperature = Some(TemperatureData {
+        id: "temp-a".to_string(),
+        id_v1: None,
+        enabled: true,
+        temperature: 19.0,
+        last_updated: now,
+        history: std::sync::Arc::new(history),
+    });
+
+    // Memory reaches back to the oldest sample, and the value before the range starts the line
+    let data = crate::views::memory_graph_data(&sensor, GraphRange::Hour, 6, now);
+    assert_eq!(data.memory_since, Some(now - Duration::hours(3)));
+    assert_eq!(data.start, now - Duration::hours(1));
+    let temperatures: Vec<f64> = data.temperatures.iter().map(|a| a.avg).collect();
+    assert_eq!(temperatures, vec![18.0, 19.0]);
+    assert_eq!(data.temperatures[0].start, data.start);
+
+    // A range wholly before memory is cut to nothing rather than ending before it starts
+    let range = GraphRange::Custom {
+        start: now - Duration::hours(10),
+        end: now - Duration::hours(5),
+    };
+    let data = crate::views::memory_graph_data(&sensor, range, 6, now);
+    assert_eq!((data.start, data.end), (now - Duration::hours(3), now - Duration::hours(3)));
+}
+
+#[test]
+fn test_export_writer() {
+    use super::client::Metric;
+    use super::aggregate::GraphRange;
+    use super::export::{
+        ExportFormat, ExportRequest, ExportRow, ExportTimeZone, ExportWriter, EXPORT_PATH,
+    };
+    use crate::preferences::TemperatureUnit;
+    use chrono::{TimeZone, Utc};
+
+    let row = |sensor: &str, value: f64| ExportRow {
+        sensor: sensor.to_string(),
+        device_id: "dev-a".to_string(),
+        metric: Metric::Temperature,
+        time: Utc.with_ymd_and_hms(2026, 7, 1, 12, 0, 0).unwrap(),
+        min: value,
+        avg: value,
+        max: value,
+    };
+    let zone = ExportTimeZone::Named("Europe/London".to_string());
+
+    let mut csv = ExportWriter::new(ExportFormat::Csv, zone.clone(), TemperatureUnit::Celsius);
+    let out = csv.header() + &csv.rows(&[row("Hall, \"upstairs\"", 20.5)]) + &csv.footer();
+    assert_eq!(
+        out,
+        "sensor,device_id,metric,unit,time,min,avg,max\n\
+         \"Hall, \"\"upstairs\"\"\",dev-a,temperature,°C,2026-07-01T13:00:00+01:00,20.5,20.5,20.5\n"
+    );
+
+    // Streamed in separate batches, the JSON is still one valid array
+    let mut json = ExportWriter::new(ExportFormat::Json, ExportTimeZone::Offset(0), TemperatureUnit::Celsius);
+    let out = json.header() + &json.rows(&[row("Hall", 20.0)]) + &json.rows(&[row("Den", 21.0)]) + &json.footer();
+    let parsed: Vec<serde_json::Value> = serde_json::from_str(&out).unwrap();
+    assert_eq!(parsed.len(), 2);
+    assert_eq!(parsed[1]["sensor"], "Den");
+    assert_eq!(parsed[0]["time"], "2026-07-01T12:00:00+00:00");
+
+    let empty = ExportWriter::new(ExportFormat::Json, zone.clone(), TemperatureUnit::Celsius);
+    let parsed: Vec<serde_json::Value> = serde_json::from_str(&(empty.header() + &empty.footer())).unwrap();
+    assert!(parsed.is_empty());
+
+    // Requests survive the round trip through the download URL
+    let request = ExportRequest {
+        sensor_ids: vec!["dev-a".to_string(), "dev b&c".to_string()],
+        range: GraphRange::Week,
+        bucket_minutes: 60,
+        format: ExportFormat::Json,
+        time_zone: zone.clone(),
+        temperature_unit: TemperatureUnit::Fahrenheit,
+    };
+    let url = request.url();
+    let query = url.strip_prefix(&format!("{}?", EXPORT_PATH)).unwrap();
+    assert!(!query.contains(['&', ' ', '"']));
+    assert_eq!(ExportRequest::from_query(query), Ok(request));
+
+    // Temperatures follow the preferred unit
+    let mut fahrenheit = ExportWriter::new(ExportFormat::Csv, zone.clone(), TemperatureUnit::Fahrenheit);
+    assert_eq!(
+        fahrenheit.rows(&[row("Hall", 20.0)]),
+        "Hall,dev-a,temperature,°F,2026-07-01T13:00:00+01:00,68,68,68\n"
+    );
+
+    assert!(zone.validate().is_ok());
+    assert!(ExportTimeZone::Named("Mars/Olympus".to_string()).validate().is_err());
+    assert!(ExportTimeZone::Offset(90_000).validate().is_err());
+}
+
+#[test]
+fn test_import_parse() {
+    use super::client::Metric;
+    use super::import::parse;
+
+    let now = chrono::Utc::now();
+    let csv = "device,timestamp,metric,value\n\
+        \"Hall, upstairs\",2024-03-01T12:00:00Z,temperature,19.5\n\
+        Hall,2024-03-01 12:00:00,presence,true\n\
+        Hall,2024-03-01 12:00:00,presence,1\n\
+        Hall,1709294400000,light_level,-3\n\
+        Hall,2999-01-01T00:00:00Z,motion,0\n\
+        Hall,yesterday,motion,0\n";
+    let (rows, report) = parse(csv, now);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].device, "Hall, upstairs");
+    assert_eq!((rows[1].metric, rows[1].value), (Metric::Motion, 1.0));
+    assert_eq!(rows[0].time, rows[1].time);
+    assert_eq!((report.rows, report.duplicates, report.rejected), (6, 1, 3));
+    let lines: Vec<usize> = report.errors.iter().map(|e| e.line).collect();
+    assert_eq!(lines, vec![5, 6, 7]);
+
+    // v1 logger entries keep temperatures in hundredths of a degree
+    let json = r#"[{"sensor": 6, "state": {"temperature": 2150, "lastupdated": "2024-03-01T12:00:00"}}]"#;
+    let (rows, report) = parse(json, now);
+    assert!(report.errors.is_empty());
+    assert_eq!((rows[0].device.as_str(), rows[0].metric, rows[0].value), ("6", Metric::Temperature, 21.5));
+
+    let (rows, report) = parse("time,value\n2024-03-01T12:00:00Z,1\n", now);
+    assert!(rows.is_empty());
+    assert_eq!(report.errors[0].line, 1);
+}
+
+#[test]
+fn test_daily_stats() {
+    use super::aggregate::Aggregate;
+    use super::export::ExportTimeZone;
+    use super::stats::{compute, RecordKind, StatsInput};
+    use chrono::{DateTime, Duration, NaiveDate};
+
+    let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().to_utc();
+    let bucket = |s: &str, min: f64, avg: f64, max: f64| Aggregate {
+        start: at(s),
+        min,
+        avg,
+        max,
+    };
+    let input = StatsInput {
+        temperatures: vec![
+            bucket("2026-01-09T02:00:00Z", 1.0, 2.0, 3.0),
+            bucket("2026-01-09T13:00:00Z", 5.0, 6.0, 7.0),
+            bucket("2026-01-09T20:00:00Z", -2.0, 0.0, 1.0),
+        ],
+        light_levels: vec![
+            bucket("2026-01-09T12:00:00Z", 30000.0, 30000.0, 30000.0),
+            bucket("2026-01-09T22:00:00Z", 0.0, 0.0, 0.0),
+        ],
+        occupancy: vec![bucket("2026-01-09T10:00:00Z", 0.5, 0.5, 0.5)],
+        // A snapshot repeating the motion doesn't start a new event
+        motions: vec![
+            (at("2026-01-09T10:00:00Z"), 1.0),
+            (at("2026-01-09T10:05:00Z"), 1.0),
+            (at("2026-01-09T10:20:00Z"), 0.0),
+            (at("2026-01-09T10:40:00Z"), 1.0),
+            (at("2026-01-09T10:45:00Z"), 0.0),
+        ],
+        bucket: Duration::hours(1),
+    };
+    let first_day = NaiveDate::from_ymd_opt(2026, 1, 9).unwrap();
+    let (days, records) = compute(
+        &input,
+        &ExportTimeZone::Offset(0),
+        first_day,
+        at("2026-01-08T00:00:00Z"),
+        at("2026-01-10T12:00:00Z"),
+    );
+
+    assert_eq!(days.len(), 2);
+    let day = &days[0];
+    assert_eq!((day.min_temperature, day.max_temperature), (Some(-2.0), Some(7.0)));
+    // Each temperature holds for the two hours after it until the sensor counts as silent
+    assert!((day.mean_temperature.unwrap() - 8.0 / 3.0).abs() < 1e-9);
+    assert_eq!(day.daylight_hours, Some(3.0));
+    assert_eq!((day.motion_events, day.occupied_minutes), (Some(2), Some(30.0)));
+    // The dark reading at 22:00 carries past midnight, but the last temperature doesn't
+    assert_eq!((days[1].min_temperature, days[1].motion_events), (None, None));
+    assert_eq!(days[1].daylight_hours, Some(0.0));
+
+    let found: Vec<(RecordKind, f64)> = records.iter().map(|r| (r.kind, r.value)).collect();
+    assert_eq!(
+        found,
+        vec![
+            (RecordKind::ColdestNight, -2.0),
+            (RecordKind::WarmestDay, 7.0),
+            (RecordKind::LongestDaylight, 3.0),
+            (RecordKind::MostActiveDay, 30.0),
+            (RecordKind::LongestMotion, 20.0),
+        ]
+    );
+    assert_eq!(records[0].date, first_day);
+}
+
+#[test]
+fn test_daily_stats_carry_forward() {
+    use super::aggregate::Aggregate;
+    use super::export::ExportTimeZone;
+    use super::stats::{compute, RecordKind, StatsInput};
+    use chrono::{DateTime, Duration, NaiveDate};
+
+    let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().to_utc();
+    let level = |s: &str, value: f64| Aggregate {
+        start: at(s),
+        min: value,
+        avg: value,
+        max: value,
+    };
+    // Light level is only reported on change, so one bright reading covers the quiet buckets after
+    // it. Then the sensor goes silent for days, which are left without stats.
+    let input = StatsInput {
+        temperatures: vec![level("2026-03-02T18:00:00Z", 20.0)],
+        light_levels: vec![
+            level("2026-03-02T08:00:00Z", 30000.0),
+            level("2026-03-02T10:00:00Z", 0.0),
+            level("2026-03-02T18:00:00Z", 30000.0),
+        ],
+        occupancy: Vec::new(),
+        motions: Vec::new(),
+        bucket: Duration::minutes(30),
+    };
+    let day = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+    let now = at("2026-03-04T20:00:00Z");
+    let (days, records) = compute(&input, &ExportTimeZone::Offset(0), day, now, now);
+    assert_eq!(days.len(), 3);
+    // 08:00 to 10:00, then 18:00 until the sensor counts as silent two hours later
+    assert_eq!(days[0].daylight_hours, Some(4.5));
+    assert_eq!(days[0].mean_temperature, Some(20.0));
+    for later in &days[1..] {
+        assert_eq!((later.daylight_hours, later.mean_temperature), (None, None));
+    }
+    assert!(records
+        .iter()
+        .any(|r| r.kind == RecordKind::LongestDaylight && r.date == day && r.value == 4.5));
+}
+
+/// Checks run against every storage backend
+async fn storage_suite(storage: super::storage::Storage) {
+    use super::aggregate::bucket_start;
+    use super::client::Metric;
+    use super::retention::{maintain, RetentionPolicy};
+    use super::storage::Reading;
+    use chrono::{Duration, DurationRound, Utc};
+
+    // SQLite keeps millisecond precision
+    let now = Utc::now().duration_trunc(Duration::milliseconds(1)).unwrap();
+    let reading = |minutes_ago: i64, value: f64| Reading {
+        resource_id: "temp-a".to_string(),
+        device_id: "dev-a".to_string(),
+        metric: Metric::Temperature,
+        time: now - Duration::minutes(minutes_ago),
+        value,
+    };
+    let readings = [reading(90, 18.0), reading(30, 19.0), reading(10, 20.0)];
+    assert_eq!(storage.insert_readings(&readings).await.unwrap(), 3);
+    // Re-recording the same readings is a no-op
+    assert_eq!(storage.insert_readings(&readings).await.unwrap(), 0);
+    assert!(storage.has_readings("temp-a").await.unwrap());
+    assert!(!storage.has_readings("temp-b").await.unwrap());
+
+    // The reading before the range is included as the starting value
+    let series = storage
+        .read_readings("temp-a", now - Duration::hours(1), now)
+        .await
+        .unwrap();
+    let values: Vec<f64> = series.iter().map(|(_, v)| *v).collect();
+    assert_eq!(values, vec![18.0, 19.0, 20.0]);
+    assert_eq!(series[2].0, now - Duration::minutes(10));
+
+    // Lookups by v1 id read the same rows
+    storage
+        .upsert_v1_ids(&[(7, "temp-a".to_string())])
+        .await
+        .unwrap();
+    let series = storage
+        .read_v1_readings(7, Metric::Temperature, now - Duration::hours(1), now)
+        .await
+        .unwrap();
+    let values: Vec<f64> = series.iter().map(|(_, v)| *v).collect();
+    assert_eq!(values, vec![19.0, 20.0]);
+
+    // Ten-minute buckets each hold one reading; the range excludes the 90-minute-old one
+    let aggregates = storage
+        .read_aggregates("temp-a", now - Duration::hours(1), now, Duration::minutes(10))
+        .await
+        .unwrap();
+    let averages: Vec<f64> = aggregates.iter().map(|a| a.avg).collect();
+    assert_eq!(averages, vec![19.0, 20.0]);
+    assert!(aggregates.iter().all(|a| a.min == a.avg && a.max == a.avg));
+
+    // Maintenance rolls readings up hourly, then drops those over a day old except the last one
+    let policy = RetentionPolicy::parse("raw:1d,1h:forever").unwrap();
+    let hour = bucket_start(now, Duration::hours(1));
+    let old = |hours_ago: i64, minutes: i64, value: f64| Reading {
+        resource_id: "temp-r".to_string(),
+        device_id: "dev-r".to_string(),
+        metric: Metric::Temperature,
+        time: hour - Duration::hours(hours_ago) + Duration::minutes(minutes),
+        value,
+    };
+    let readings = [old(48, 0, 10.0), old(48, 30, 20.0), old(47, 0, 30.0), old(2, 0, 40.0)];
+    storage.insert_readings(&readings).await.unwrap();
+
+    let report = maintain(&storage, &policy, now).await.unwrap();
+    assert_eq!(report.readings_deleted, 2);
+    let rollups = storage
+        .read_rollups("temp-r", Duration::hours(1), hour - Duration::hours(48), hour, Duration::hours(1))
+        .await
+        .unwrap();
+    let rollups: Vec<(f64, f64, f64)> = rollups.iter().map(|a| (a.min, a.avg, a.max)).collect();
+    assert_eq!(rollups, vec![(10.0, 15.0, 20.0), (30.0, 30.0, 30.0), (40.0, 40.0, 40.0)]);
+
+    // Nothing new to roll up, and the kept reading still gives the value before the raw history
+    let report = maintain(&storage, &policy, now).await.unwrap();
+    assert_eq!((report.rollups_written, report.readings_deleted), (0, 0));
+    let series = storage
+        .read_readings("temp-r", hour - Duration::hours(24), now)
+        .await
+        .unwrap();
+    let values: Vec<f64> = series.iter().map(|(_, v)| *v).collect();
+    assert_eq!(values, vec![30.0, 40.0]);
+
+    // An import into the pruned span adds rollups where there were none, but leaves those summarising
+    // deleted readings alone
+    let mut shed = test_sensor("dev-r", "motion-r");
+    shed.name = "Shed".to_string();
+    shed.temperature = Some(super::client::TemperatureData {
+        id: "temp-r".to_string(),
+        id_v1: None,
+        enabled: true,
+        temperature: 40.0,
+        last_updated: now,
+        history: Default::default(),
+    });
+    let row = |time, value| super::import::ImportRow {
+        line: 1,
+        time,
+        device: "Shed".to_string(),
+        metric: Metric::Temperature,
+        value,
+    };
+    let rows = [
+        row(hour - Duration::hours(48) + Duration::minutes(15), 25.0),
+        row(hour - Duration::hours(40), 26.0),
+    ];
+    let report = super::import::import_batch(&storage, &[shed], &rows).await.unwrap();
+    assert_eq!(report.imported, 2);
+    maintain(&storage, &policy, now).await.unwrap();
+    let rollups = storage
+        .read_rollups("temp-r", Duration::hours(1), hour - Duration::hours(48), hour, Duration::hours(1))
+        .await
+        .unwrap();
+    let rollups: Vec<(i64, f64, f64)> = rollups
+        .iter()
+        .map(|a| ((hour - a.start).num_hours(), a.min, a.max))
+        .collect();
+    assert_eq!(
+        rollups,
+        vec![(48, 10.0, 20.0), (47, 30.0, 30.0), (40, 26.0, 26.0), (2, 40.0, 40.0)]
+    );
+
+    // Imports match devices by v1 number or name and skip rows already recorded
+    let mut sensor = test_sensor("dev-i", "motion-i");
+    sensor.name = "Porch".to_string();
+    sensor.motion.as_mut().unwrap().id_v1 = Some("/sensors/12".to_string());
+    let (rows, _) = super::import::parse(
+        &format!(
+            "timestamp,device,metric,value\n{t},12,motion,1\n{t},porch,temperature,20\n{t},Attic,motion,0\n",
+            t = (now - Duration::hours(1)).to_rfc3339()
+        ),
+        now,
+    );
+    let report = super::import::import_batch(&storage, &[sensor.clone()], &rows)
+        .await
+        .unwrap();
+    assert_eq!((report.imported, report.rejected), (1, 2));
+    assert!(storage.has_readings("motion-i").await.unwrap());
+    let report = super::import::import_batch(&storage, &[sensor.clone()], &rows[..1])
+        .await
+        .unwrap();
+    assert_eq!((report.imported, report.duplicates), (0, 1));
+
+    // Rows are checked again, as a client may send them without parsing
+    let forged = |time, value| super::import::ImportRow {
+        line: 1,
+        time,
+        device: "12".to_string(),
+        metric: Metric::Motion,
+        value,
+    };
+    let forged = [forged(now - Duration::minutes(5), 7.0), forged(now + Duration::days(1), 1.0)];
+    let report = super::import::import_batch(&storage, &[sensor], &forged)
+        .await
+        .unwrap();
+    assert_eq!((report.imported, report.rejected), (0, 2));
+}
+
+#[sqlx::test(migrations = "./migrations/postgres")]
+async fn test_postgres_storage(pool: sqlx::PgPool) {
+    storage_suite(super::storage::Storage::Postgres(pool)).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_storage() {
+    let storage = super::storage::Storage::connect("sqlite::memory:", 5)
+        .await
+        .unwrap();
+    storage.migrate().await.unwrap();
+    storage_suite(storage).await;
+}
@@ -2,11 +2,15 @@
 // need dioxus
 use dioxus::prelude::*;
 
-use views::{EventLog, Graphs, Home, Navbar, Sensors};
+use hue::aggregate::GraphRange;
+use views::{Compare, EventLog, Graphs, Home, Import, Navbar, Sensors, Settings, Stats};
 
 /// Define a hue module, for all interactions with the Hue Bridge
 mod hue;
 
+/// Define a preferences module for per-user display settings (units, time format, time zone)
+mod preferences;
+
 /// Define a components module that contains all shared components for our app.
 mod components;
 /// Define a views module that contains the UI for all Layouts and Routes for our app.
@@ -27,12 +31,20 @@ enum Route {
         // the component for that route will be rendered. The component name that is rendered defaults to the variant name.
         #[route("/")]
         Sensors {},
-        #[route("/sensors/:sensor_id")]
-        Graphs { sensor_id: String },
+        #[route("/sensors/:sensor_id?:range")]
+        Graphs { sensor_id: String, range: GraphRange },
+        #[route("/sensors/:sensor_id/stats")]
+        Stats { sensor_id: String },
+        #[route("/compare?:range")]
+        Compare { range: GraphRange },
+        #[route("/import")]
+        Import {},
         #[route("/events")]
         EventLog {},
         #[route("/home")]
         Home {},
+        #[route("/settings")]
+        Settings {},
 }
 
 // We can import assets in dioxus with the `asset!` macro. This macro takes a path to an asset relative to the crate root.
@@ -44,6 +56,48 @@ const TAILWIND_CSS: Asset = asset!("/assets/tailwind.css");
 fn main() {
     // dioxus_logger::init(tracing::Level::INFO).expect("failed to init logger");
 
+    // `huebot migrate` applies database migrations, `huebot backfill [days]` copies history logged
+    // by v1 sensor id into huebot's own table and `huebot import <file>` loads readings from a CSV or
+    // JSON file. Each exits when done.
+    #[cfg(feature = "server")]
+    if let Some(command) = std::env::args().nth(1) {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let result = match command.as_str() {
+            "migrate" => Some(
+                rt.block_on(hue::storage::migrate_from_env())
+                    .map(|()| "Database migrations applied.".to_string()),
+            ),
+            "backfill" => {
+                let days = std::env::args().nth(2).and_then(|d| d.parse().ok());
+                Some(
+                    rt.block_on(hue::recorder::backfill(days))
+                        .map(|copied| format!("Backfilled {} readings.", copied)),
+                )
+            }
+            "import" => match std::env::args().nth(2) {
+                Some(path) => Some(rt.block_on(hue::import::import_file(&path)).map(|report| {
+                    format!(
+                        "Imported {} of {} rows ({} duplicates, {} rejected).",
+                        report.imported, report.rows, report.duplicates, report.rejected
+                    )
+                })),
+                None => Some(Err("usage: huebot import <file>".to_string())),
+            },
+            _ => None,
+        };
+        match result {
+            Some(Ok(message)) => {
+                println!("{}", message);
+                std::process::exit(0);
+            }
+            Some(Err(e)) => {
+                eprintln!("huebot {} failed: {}", command, e);
+                std::process::exit(1);
+            }
+            None => {}
+        }
+    }
+
     // The `launch` function is the main entry point for a dioxus app. It takes a component and renders it with the platform feature
     // you have enabled
     #[cfg(feature = "server")]
@@ -70,7 +124,27 @@ fn main() {
         });
     });
 
+    // Record sensor history to the database if enabled
+    #[cfg(feature = "server")]
+    hue::recorder::spawn();
+    // Roll up and expire old history
+    #[cfg(feature = "server")]
+    hue::retention::spawn();
+
+    #[cfg(not(feature = "server"))]
     dioxus::launch(App);
+
+    // Start the bridge listener on the server's own runtime, so it runs from startup and the
+    // recorder and clients all share it. Exports are a plain GET route so browsers can download
+    // them straight to disk.
+    #[cfg(feature = "server")]
+    dioxus::serve(|| async move {
+        hue::start_event_listener();
+        Ok(dioxus::server::router(App).route(
+            hue::export::EXPORT_PATH,
+            dioxus::fullstack::axum::routing::get(views::export_download),
+        ))
+    });
 }
 
 /// App is the main component of our app. Components are the building blocks of dioxus apps. Each component is a function
@@ -79,6 +153,11 @@ fn main() {
 /// Components should be annotated with `#[component]` to support props, better error messages, and autocomplete
 #[component]
 fn App() -> Element {
+    // Make the user's display preferences available to every component
+    preferences::use_preferences_provider();
+    // Share a single Hue event stream between every component on the page
+    hue::subscription::use_event_subscriptions_provider();
+
     // The `rsx!` macro lets us define HTML inside of rust. It expands to an Element with all of our HTML inside.
     rsx! {
         // Ensure the app scales correctly on mobile devices
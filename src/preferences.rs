@@ -0,0 +1,211 @@
+use crate::hue::export::ExportTimeZone;
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(not(feature = "web"), allow(dead_code))]
+const STORAGE_KEY: &str = "huebot.preferences";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Celsius => "°C",
+            Self::Fahrenheit => "°F",
+        }
+    }
+
+    /// Converts a Celsius reading into this unit
+    pub fn convert(&self, celsius: f64) -> f64 {
+        match self {
+            Self::Celsius => celsius,
+            Self::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum HourFormat {
+    #[default]
+    H24,
+    H12,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum DateFormat {
+    /// 2024-01-31
+    #[default]
+    Iso,
+    /// 31/01/2024
+    DayMonthYear,
+    /// 01/31/2024
+    MonthDayYear,
+}
+
+impl DateFormat {
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            Self::Iso => "%Y-%m-%d",
+            Self::DayMonthYear => "%d/%m/%Y",
+            Self::MonthDayYear => "%m/%d/%Y",
+        }
+    }
+}
+
+/// Per-user display preferences, persisted in the browser's localStorage
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    pub temperature_unit: TemperatureUnit,
+    pub hour_format: HourFormat,
+    pub date_format: DateFormat,
+    /// IANA time zone name; `None` uses the browser's local time zone
+    pub time_zone: Option<String>,
+    /// Rooms collapsed on the Sensors page
+    pub collapsed_rooms: Vec<String>,
+}
+
+impl Preferences {
+    /// Converts a Celsius reading into the preferred unit
+    pub fn convert_temperature(&self, celsius: f64) -> f64 {
+        self.temperature_unit.convert(celsius)
+    }
+
+    pub fn format_temperature(&self, celsius: f64) -> String {
+        format!(
+            "{:.1}{}",
+            self.convert_temperature(celsius),
+            self.temperature_unit.symbol()
+        )
+    }
+
+    /// Converts a UTC timestamp into the display time zone
+    pub fn localize(&self, t: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self.time_zone.as_deref().and_then(|tz| tz.parse::<Tz>().ok()) {
+            Some(tz) => t.with_timezone(&tz).fixed_offset(),
+            None => t.with_timezone(&Local).fixed_offset(),
+        }
+    }
+
+    /// Interprets a wall-clock time in the display time zone. Times skipped by a DST change are
+    /// rejected; ambiguous ones take the earlier instant.
+    pub fn to_utc(&self, t: NaiveDateTime) -> Option<DateTime<Utc>> {
+        match self.time_zone.as_deref().and_then(|tz| tz.parse::<Tz>().ok()) {
+            Some(tz) => tz.from_local_datetime(&t).earliest().map(|t| t.to_utc()),
+            None => Local.from_local_datetime(&t).earliest().map(|t| t.to_utc()),
+        }
+    }
+
+    pub fn format_time(&self, t: DateTime<Utc>) -> String {
+        let pattern = match self.hour_format {
+            HourFormat::H24 => "%H:%M:%S",
+            HourFormat::H12 => "%-I:%M:%S %p",
+        };
+        self.localize(t).format(pattern).to_string()
+    }
+
+    pub fn format_short_time(&self, t: DateTime<Utc>) -> String {
+        let pattern = match self.hour_format {
+            HourFormat::H24 => "%H:%M",
+            HourFormat::H12 => "%-I:%M %p",
+        };
+        self.localize(t).format(pattern).to_string()
+    }
+
+    pub fn format_date(&self, t: DateTime<Utc>) -> String {
+        self.localize(t)
+            .format(self.date_format.pattern())
+            .to_string()
+    }
+
+    pub fn format_datetime(&self, t: DateTime<Utc>) -> String {
+        format!("{} {}", self.format_date(t), self.format_time(t))
+    }
+
+    /// Splits an axis label into the hour part and the minutes part (hidden on small screens)
+    pub fn format_axis_time(&self, t: DateTime<Utc>) -> (String, String) {
+        let local = self.localize(t);
+        match self.hour_format {
+            HourFormat::H24 => (
+                local.format("%H").to_string(),
+                local.format(":%M").to_string(),
+            ),
+            HourFormat::H12 => (
+                local.format("%-I").to_string(),
+                local.format(":%M%P").to_string(),
+            ),
+        }
+    }
+
+    /// The zone exports are written in: the chosen zone, or else the browser's current offset
+    pub fn export_time_zone(&self) -> ExportTimeZone {
+        match self.time_zone.as_deref().filter(|tz| tz.parse::<Tz>().is_ok()) {
+            Some(tz) => ExportTimeZone::Named(tz.to_string()),
+            None => ExportTimeZone::Offset(Local::now().offset().local_minus_utc()),
+        }
+    }
+
+    /// Splits a date axis label into the day and the month (hidden on small screens)
+    pub fn format_axis_date(&self, t: DateTime<Utc>) -> (String, String) {
+        let local = self.localize(t);
+        (local.format("%-d").to_string(), local.format(" %b").to_string())
+    }
+
+    #[cfg(feature = "web")]
+    fn load() -> Option<Self> {
+        let storage = web_sys::window()?.local_storage().ok()??;
+        let json = storage.get_item(STORAGE_KEY).ok()??;
+        serde_json::from_str(&json).ok()
+    }
+
+    #[cfg(not(feature = "web"))]
+    fn load() -> Option<Self> {
+        None
+    }
+
+    /// Expands or collapses a room on the Sensors page
+    pub fn toggle_room(&mut self, room_id: &str) {
+        if let Some(pos) = self.collapsed_rooms.iter().position(|id| id == room_id) {
+            self.collapsed_rooms.remove(pos);
+        } else {
+            self.collapsed_rooms.push(room_id.to_string());
+        }
+    }
+
+    /// Persists the preferences to localStorage
+    pub fn save(&self) {
+        #[cfg(feature = "web")]
+        {
+            if let (Some(storage), Ok(json)) = (
+                web_sys::window().and_then(|w| w.local_storage().ok().flatten()),
+                serde_json::to_string(self),
+            ) {
+                let _ = storage.set_item(STORAGE_KEY, &json);
+            }
+        }
+    }
+}
+
+/// Provides the preferences signal to the whole app. Stored preferences are loaded after mount so that
+/// the server-rendered markup (which always uses the defaults) hydrates cleanly.
+pub fn use_preferences_provider() -> Signal<Preferences> {
+    let mut prefs = use_context_provider(|| Signal::new(Preferences::default()));
+    use_effect(move || {
+        if let Some(stored) = Preferences::load() {
+            prefs.set(stored);
+        }
+    });
+    prefs
+}
+
+/// Returns the app-wide preferences signal
+pub fn use_preferences() -> Signal<Preferences> {
+    use_context::<Signal<Preferences>>()
+}
@@ -0,0 +1,191 @@
+use super::graphs::{get_comparison_data, get_sensor_names};
+use crate::components::{ComparisonGraph, GraphSeries, HistoryPoint, RangePicker};
+use crate::hue::aggregate::GraphRange;
+use crate::hue::client::light_level_to_lux;
+use crate::hue::models::Metric;
+use crate::preferences::use_preferences;
+use crate::Route;
+use dioxus::prelude::*;
+
+/// Width of the graph's drawing area; half as many buckets are requested
+const CHART_WIDTH: u32 = 1000;
+
+/// Series colours, assigned in the order sensors are chosen
+const SERIES_COLORS: [&str; 8] = [
+    "#60a5fa", // blue-400
+    "#f87171", // red-400
+    "#34d399", // emerald-400
+    "#fbbf24", // amber-400
+    "#a78bfa", // violet-400
+    "#f472b6", // pink-400
+    "#22d3ee", // cyan-400
+    "#fb923c", // orange-400
+];
+
+const METRICS: [(Metric, &str); 3] = [
+    (Metric::Temperature, "Temperature"),
+    (Metric::LightLevel, "Light"),
+    (Metric::Motion, "Motion"),
+];
+
+/// The Compare page component that will be rendered when the current route is `[Route::Compare]`.
+/// Overlays one metric from several sensors over the same range.
+#[component]
+pub fn Compare(range: GraphRange) -> Element {
+    let prefs = use_preferences()();
+    let sensors = use_resource(get_sensor_names);
+    let mut selected = use_signal(Vec::<String>::new);
+    let mut metric = use_signal(|| Metric::Temperature);
+    let mut log_scale = use_signal(|| true);
+
+    let data = use_resource(use_reactive!(|range| async move {
+        let sensor_ids = selected();
+        let metric = metric();
+        if sensor_ids.is_empty() {
+            return Ok(None);
+        }
+        get_comparison_data(sensor_ids, metric, range, (CHART_WIDTH / 2) as usize)
+            .await
+            .map(Some)
+    }));
+
+    let choice_class = |active: bool| {
+        if active {
+            "px-3 py-1 rounded bg-blue-600 text-white text-sm"
+        } else {
+            "px-3 py-1 rounded bg-gray-100 dark:bg-gray-700 hover:bg-gray-200 dark:hover:bg-gray-600 text-sm"
+        }
+    };
+
+    rsx! {
+        div { class: "w-full p-4 pb-20 max-w-[100vw] overflow-x-hidden",
+            div { class: "max-w-7xl mx-auto",
+                h1 { class: "text-2xl font-bold mb-6", "Compare Sensors" }
+                RangePicker {
+                    key: "{range}",
+                    range,
+                    to: move |range| Route::Compare { range },
+                }
+                div { class: "p-4 bg-white dark:bg-gray-800 rounded-lg shadow w-full mb-8 flex flex-col gap-4",
+                    div { class: "flex flex-wrap items-center gap-2",
+                        for (value, label) in METRICS {
+                            button {
+                                class: choice_class(metric() == value),
+                                onclick: move |_| metric.set(value),
+                                "{label}"
+                            }
+                        }
+                        if metric() == Metric::LightLevel {
+                            label { class: "ml-auto flex items-center gap-2 text-sm text-gray-600 dark:text-gray-400",
+                                input {
+                                    r#type: "checkbox",
+                                    checked: log_scale(),
+                                    onchange: move |e| log_scale.set(e.checked()),
+                                }
+                                "Log scale"
+                            }
+                        }
+                    }
+                    div { class: "flex flex-wrap gap-x-4 gap-y-2",
+                        {
+                            match &*sensors.read() {
+                                Some(Ok(names)) => rsx! {
+                                    for (device_id, name) in names.clone() {
+                                        label { class: "flex items-center gap-2 text-sm",
+                                            input {
+                                                r#type: "checkbox",
+                                                checked: selected.read().contains(&device_id),
+                                                onchange: move |e| {
+                                                    let mut ids = selected.write();
+                                                    ids.retain(|id| id != &device_id);
+                                                    if e.checked() {
+                                                        ids.push(device_id.clone());
+                                                    }
+                                                },
+                                            }
+                                            "{name}"
+                                        }
+                                    }
+                                },
+                                Some(Err(e)) => rsx! { span { class: "text-sm text-red-600", "{e}" } },
+                                None => rsx! { span { class: "text-sm text-gray-400 italic", "Loading sensors…" } },
+                            }
+                        }
+                    }
+                }
+                div { class: "p-4 bg-white dark:bg-gray-800 rounded-lg shadow w-full",
+                    {
+                        match &*data.read() {
+                            Some(Ok(Some(data))) => {
+                                // The data may be for the previous metric while the new one loads
+                                let unit = match data.metric {
+                                    Metric::Temperature => prefs.temperature_unit.symbol().to_string(),
+                                    Metric::LightLevel => "lx".to_string(),
+                                    Metric::Motion => "%".to_string(),
+                                };
+                                let series: Vec<GraphSeries> = data
+                                    .series
+                                    .iter()
+                                    .map(|s| {
+                                        // Colours follow the selection so a sensor keeps its colour across metrics
+                                        let position = selected.read().iter().position(|id| id == &s.device_id).unwrap_or(0);
+                                        let history = s
+                                            .points
+                                            .iter()
+                                            .map(|a| HistoryPoint {
+                                                value: match data.metric {
+                                                    Metric::Temperature => prefs.convert_temperature(a.avg),
+                                                    Metric::LightLevel => light_level_to_lux(a.avg.round() as i32),
+                                                    Metric::Motion => a.avg * 100.0,
+                                                },
+                                                time: a.start,
+                                            })
+                                            .collect();
+                                        GraphSeries {
+                                            name: s.name.clone(),
+                                            color: SERIES_COLORS[position % SERIES_COLORS.len()].to_string(),
+                                            history,
+                                        }
+                                    })
+                                    .collect();
+                                let missing = data.missing.join(", ");
+                                rsx! {
+                                    div { class: "h-80 w-full",
+                                        ComparisonGraph {
+                                            series,
+                                            start: data.start,
+                                            end: data.end,
+                                            width: CHART_WIDTH,
+                                            unit,
+                                            log_scale: data.metric == Metric::LightLevel && log_scale(),
+                                        }
+                                    }
+                                    if !data.missing.is_empty() {
+                                        p { class: "text-sm text-gray-500 mt-2", "No {unit_name(data.metric)} history for {missing}" }
+                                    }
+                                }
+                            }
+                            Some(Ok(None)) => rsx! {
+                                div { class: "h-80 flex items-center justify-center text-gray-400 italic", "Choose sensors to compare" }
+                            },
+                            Some(Err(e)) => rsx! {
+                                div { class: "h-80 flex items-center justify-center text-red-600 text-sm", "{e}" }
+                            },
+                            None => rsx! {
+                                div { class: "h-80 flex items-center justify-center text-gray-400 italic", "Loading…" }
+                            },
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn unit_name(metric: Metric) -> &'static str {
+    match metric {
+        Metric::Motion => "motion",
+        Metric::Temperature => "temperature",
+        Metric::LightLevel => "light",
+    }
+}
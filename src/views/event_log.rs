@@ -17,8 +17,19 @@ pub fn EventLog() -> Element {
     let mut events = use_signal(Vec::<(String, String)>::new);
     let names = use_resource(get_device_names);
 
+    let connection = crate::hue::use_connection_state();
+
+    // Log connection failures alongside the events
+    use_effect(move || {
+        if let crate::hue::ConnectionState::Error(msg) = connection() {
+            events.with_mut(|evs| {
+                evs.push(("Error".to_string(), format!("Connection error: {}", msg)))
+            });
+        }
+    });
+
     crate::hue::use_hue_event_handler(
-        false,
+        Default::default(),
         move |event| {
             let id = event.resource_id();
             let owner_rid = event.owner_rid();
@@ -66,18 +77,32 @@ pub fn EventLog() -> Element {
                 }
             });
         },
-        move |msg| {
+        move || {
             events.with_mut(|evs| {
-                evs.push(("Error".to_string(), format!("Connection error: {}", msg)))
+                evs.push((
+                    "System".to_string(),
+                    "Missed events while disconnected; log may be incomplete".to_string(),
+                ))
             });
         },
     );
 
+    let (status, status_class) = match connection() {
+        crate::hue::ConnectionState::Connecting => ("Connecting…", "text-gray-500"),
+        crate::hue::ConnectionState::Connected => ("Connected", "text-green-600 dark:text-green-400"),
+        crate::hue::ConnectionState::Reconnecting => ("Reconnecting…", "text-yellow-600"),
+        crate::hue::ConnectionState::Error(_) => ("Disconnected", "text-red-600 dark:text-red-400"),
+        crate::hue::ConnectionState::Idle => ("Paused", "text-gray-500"),
+    };
+
     rsx! {
         div { class: "container mx-auto p-4",
             div { class: "flex justify-between items-baseline mb-4",
                 h1 { class: "text-2xl font-bold", "Hue Event Log (Streaming)" }
-                Clock {}
+                div { class: "flex items-center gap-4",
+                    span { class: "text-sm {status_class}", "{status}" }
+                    Clock {}
+                }
             }
             div { class: "bg-black text-gray-300 p-4 rounded-lg font-mono text-xs h-[32rem] overflow-y-auto",
                 for (name , event) in events.read().iter().rev() {
@@ -1,5 +1,13 @@
-use crate::components::{HistoryPoint, SensorDataGraph};
-use chrono::{DateTime, Duration, Utc};
+use crate::components::{HistoryPoint, RangePicker, SensorDataGraph, SensorSettingsForm};
+use crate::hue::aggregate::{Aggregate, GraphRange};
+#[cfg(feature = "server")]
+use crate::hue::aggregate::{duty_cycle, rollup_split};
+use crate::hue::client::light_level_to_lux;
+use crate::hue::export::{ExportFormat, ExportRequest};
+use crate::hue::models::Metric;
+use crate::preferences::use_preferences;
+use crate::Route;
+use chrono::{DateTime, Utc};
 use dioxus::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -12,127 +20,892 @@ pub struct GraphPoint<T> {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SensorGraphData {
     pub name: String,
-    pub motions: Vec<GraphPoint<bool>>,
-    pub temperatures: Vec<GraphPoint<f32>>,
-    pub light_levels: Vec<GraphPoint<i32>>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Fraction of each bucket during which motion was detected
+    pub motions: Vec<GraphPoint<f64>>,
+    /// Calibrated temperatures in °C
+    pub temperatures: Vec<Aggregate>,
+    /// Raw bridge light levels
+    pub light_levels: Vec<Aggregate>,
+    /// Set when no database is configured, to the start of the history held in memory
+    pub memory_since: Option<DateTime<Utc>>,
 }
 
+/// Width of the graphs' drawing area. Half as many buckets are requested, so long ranges stay small.
+const CHART_WIDTH: u32 = 1000;
+/// Upper limit on the buckets a client may request
+const MAX_BUCKETS: usize = 2000;
+
+/// Where one service's history is read from
+#[cfg(feature = "server")]
+#[derive(Debug, Clone)]
+pub(super) enum HistorySource {
+    /// `huebot_readings` and its rollups, by v2 resource id
+    Recorded(String),
+    /// Readings logged elsewhere by v1 sensor number, read live through the `sensor_*` functions
+    V1(i32, Metric),
+}
+
+#[cfg(feature = "server")]
+impl HistorySource {
+    /// Readings between `start` and `end`. Recorded history is preceded by the last reading before
+    /// `start`.
+    pub(super) async fn read(
+        &self,
+        storage: &crate::hue::storage::Storage,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<(DateTime<Utc>, f64)>, ServerFnError> {
+        match self {
+            Self::Recorded(resource_id) => storage.read_readings(resource_id, start, end).await,
+            Self::V1(v1_id, metric) => storage.read_v1_readings(*v1_id, *metric, start, end).await,
+        }
+        .map_err(|e| ServerFnError::new(e.to_string()))
+    }
+}
+
+/// Where each of a sensor's services is read from
+#[cfg(feature = "server")]
+pub(super) struct HistorySources {
+    pub motion: Option<HistorySource>,
+    pub temperature: Option<HistorySource>,
+    pub light: Option<HistorySource>,
+    /// Whether any service has recorded readings or a v1 id to read by
+    pub found: bool,
+}
+
+/// Finds where a sensor's history is kept. A service with nothing recorded under its v2 resource id
+/// is read by v1 id instead, so graphs keep following an external v1 logger until the recorder or
+/// `huebot backfill` fills `huebot_readings`.
+#[cfg(feature = "server")]
+pub(super) async fn history_sources(
+    storage: &crate::hue::storage::Storage,
+    sensor: &crate::hue::client::CompositeSensor,
+) -> Result<HistorySources, ServerFnError> {
+    let v1_services = crate::hue::storage::v1_services(sensor);
+    let mut found = false;
+    let mut sources = [None, None, None];
+    let ids = [
+        sensor.motion.as_ref().map(|m| &m.id),
+        sensor.temperature.as_ref().map(|t| &t.id),
+        sensor.light.as_ref().map(|l| &l.id),
+    ];
+    for (source, id) in sources.iter_mut().zip(ids) {
+        let Some(id) = id else { continue };
+        let recorded = storage
+            .has_readings(id)
+            .await
+            .map_err(|e| ServerFnError::new(e.to_string()))?;
+        let v1 = v1_services.iter().find(|(_, resource_id, _)| resource_id == id);
+        *source = Some(match v1 {
+            Some((v1_id, _, metric)) if !recorded => {
+                found = true;
+                HistorySource::V1(*v1_id, *metric)
+            }
+            _ => {
+                found |= recorded;
+                HistorySource::Recorded(id.clone())
+            }
+        });
+    }
+    let [motion, temperature, light] = sources;
+    Ok(HistorySources {
+        motion,
+        temperature,
+        light,
+        found,
+    })
+}
+
+/// Explains a sensor's empty history when nothing is recording it and it has no v1 ids to read by
+#[cfg(feature = "server")]
+pub(super) fn check_history_source(
+    sensor: &crate::hue::client::CompositeSensor,
+    sources: &HistorySources,
+) -> Result<(), ServerFnError> {
+    if sources.motion.is_none() && sources.temperature.is_none() && sources.light.is_none() {
+        return Err(ServerFnError::new(format!(
+            "{} has no motion, temperature or light services to graph",
+            sensor.name
+        )));
+    }
+    if sources.found || crate::hue::recorder::enabled() {
+        return Ok(());
+    }
+    Err(ServerFnError::new(format!(
+        "No history has been recorded for {}. Set HISTORY_RECORDER=true to record readings, \
+         or import earlier ones with `huebot import`.",
+        sensor.name
+    )))
+}
+
+/// Rounds a bucket up to a whole number of rollups when reading from them
+#[cfg(feature = "server")]
+pub(super) fn fit_bucket(bucket: chrono::Duration, rollup: Option<chrono::Duration>) -> chrono::Duration {
+    match rollup {
+        Some(resolution) => {
+            let per_bucket =
+                (bucket.num_seconds() + resolution.num_seconds() - 1) / resolution.num_seconds();
+            resolution * per_bucket.max(1) as i32
+        }
+        None => bucket,
+    }
+}
+
+/// Reads the rollups of a resource for the buckets before the rollups' watermark, returning them
+/// and the time from which the rest must be read from raw readings
+#[cfg(feature = "server")]
+async fn read_rolled_up(
+    storage: &crate::hue::storage::Storage,
+    resource_id: &str,
+    resolution: chrono::Duration,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: chrono::Duration,
+) -> Result<(Vec<Aggregate>, DateTime<Utc>), ServerFnError> {
+    let watermark = storage
+        .rollup_watermark(resolution, resource_id)
+        .await
+        .map_err(|e| ServerFnError::new(e.to_string()))?;
+    let split = rollup_split(watermark, start, end, bucket);
+    let mut rolled_up = storage
+        .read_rollups(resource_id, resolution, start, split, bucket)
+        .await
+        .map_err(|e| ServerFnError::new(e.to_string()))?;
+    // The bucket starting at the split is read from raw readings
+    rolled_up.retain(|a| a.start < split);
+    Ok((rolled_up, split))
+}
+
+/// Reads one metric summarised into buckets. With a rollup resolution, recorded history before the
+/// bucket holding the rollups' watermark is read from them and the rest from raw readings.
+#[cfg(feature = "server")]
+async fn read_buckets(
+    storage: &crate::hue::storage::Storage,
+    source: &HistorySource,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: chrono::Duration,
+    rollup: Option<chrono::Duration>,
+) -> Result<Vec<Aggregate>, ServerFnError> {
+    let resource_id = match source {
+        HistorySource::Recorded(resource_id) => resource_id,
+        // A v1 logger keeps its own history, without rollups
+        HistorySource::V1(..) => {
+            let series = source.read(storage, start, end).await?;
+            return Ok(crate::hue::aggregate::aggregate(&series, start, end, bucket));
+        }
+    };
+    match rollup {
+        None => storage.read_aggregates(resource_id, start, end, bucket).await,
+        Some(resolution) => {
+            let (mut rolled_up, split) =
+                read_rolled_up(storage, resource_id, resolution, start, end, bucket).await?;
+            storage
+                .read_aggregates(resource_id, split, end, bucket)
+                .await
+                .map(|recent| {
+                    rolled_up.extend(recent);
+                    rolled_up
+                })
+        }
+    }
+    .map_err(|e| ServerFnError::new(e.to_string()))
+}
+
+/// Loads one metric summarised into buckets, starting from the value carried over from before the
+/// range so the line doesn't start late
+#[cfg(feature = "server")]
+pub(super) async fn load_aggregates(
+    storage: &crate::hue::storage::Storage,
+    source: &HistorySource,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: chrono::Duration,
+    rollup: Option<chrono::Duration>,
+) -> Result<Vec<Aggregate>, ServerFnError> {
+    let mut aggregates = read_buckets(storage, source, start, end, bucket, rollup).await?;
+
+    if aggregates.first().map_or(true, |a| a.start > start) {
+        let before = source.read(storage, start, start).await?;
+        if let Some((_, v)) = before.first().filter(|(t, _)| *t < start) {
+            aggregates.insert(
+                0,
+                Aggregate {
+                    start,
+                    min: *v,
+                    avg: *v,
+                    max: *v,
+                },
+            );
+        }
+    }
+    Ok(aggregates)
+}
+
+/// Loads the fraction of each bucket with motion detected. Motion is sparse (it only reports
+/// changes), so raw readings are turned into a duty cycle here; rollups already hold one.
+#[cfg(feature = "server")]
+pub(super) async fn load_motion(
+    storage: &crate::hue::storage::Storage,
+    source: &HistorySource,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: chrono::Duration,
+    rollup: Option<chrono::Duration>,
+) -> Result<Vec<Aggregate>, ServerFnError> {
+    let duty = |from: DateTime<Utc>| async move {
+        source.read(storage, from, end).await.map(|series| {
+            duty_cycle(&series, from, end, bucket)
+                .into_iter()
+                .map(|(start, on)| Aggregate {
+                    start,
+                    min: on,
+                    avg: on,
+                    max: on,
+                })
+                .collect::<Vec<_>>()
+        })
+    };
+
+    match (rollup, source) {
+        (Some(resolution), HistorySource::Recorded(resource_id)) => {
+            let (mut rolled_up, split) =
+                read_rolled_up(storage, resource_id, resolution, start, end, bucket).await?;
+            duty(split).await.map(|recent| {
+                rolled_up.extend(recent);
+                rolled_up
+            })
+        }
+        _ => duty(start).await,
+    }
+}
+
+/// Summarises the history the sensor keeps in memory, for when there is no database. The range is
+/// cut to the span memory covers, from its oldest sample.
+#[cfg(feature = "server")]
+pub(crate) fn memory_graph_data(
+    sensor: &crate::hue::client::CompositeSensor,
+    range: GraphRange,
+    buckets: usize,
+    now: DateTime<Utc>,
+) -> SensorGraphData {
+    use crate::hue::aggregate::{aggregate, bucket_size};
+
+    let oldest = [
+        sensor.motion.as_ref().and_then(|m| m.history.iter().next().map(|(t, _)| *t)),
+        sensor.temperature.as_ref().and_then(|t| t.history.iter().next().map(|(t, _)| *t)),
+        sensor.light.as_ref().and_then(|l| l.history.iter().next().map(|(t, _)| *t)),
+    ];
+    let since = oldest.into_iter().flatten().min().unwrap_or(now);
+    let (start, end) = range.bounds(now);
+    // A range wholly before the history held is cut to nothing rather than running backwards
+    let start = start.max(since);
+    let end = end.max(start);
+    let bucket = bucket_size(start, end, buckets.clamp(1, MAX_BUCKETS));
+
+    // Like load_aggregates, starting from the value carried over from before the range
+    let summarise = |series: Vec<(DateTime<Utc>, f64)>| {
+        let mut aggregates = aggregate(&series, start, end, bucket);
+        if aggregates.first().map_or(true, |a| a.start > start) {
+            if let Some((_, v)) = series.iter().rev().find(|(t, _)| *t < start) {
+                aggregates.insert(
+                    0,
+                    Aggregate {
+                        start,
+                        min: *v,
+                        avg: *v,
+                        max: *v,
+                    },
+                );
+            }
+        }
+        aggregates
+    };
+
+    let motions = sensor.motion.as_ref().map_or_else(Vec::new, |m| {
+        let series: Vec<(DateTime<Utc>, f64)> =
+            m.history.iter().map(|(t, v)| (*t, *v as i32 as f64)).collect();
+        duty_cycle(&series, start, end, bucket)
+            .into_iter()
+            .map(|(timestamp, value)| GraphPoint { timestamp, value })
+            .collect()
+    });
+    // In-memory temperatures are already calibrated
+    let temperatures = sensor.temperature.as_ref().map_or_else(Vec::new, |t| {
+        summarise(t.history.iter().copied().collect())
+    });
+    let light_levels = sensor.light.as_ref().map_or_else(Vec::new, |l| {
+        summarise(l.history.iter().map(|(t, v)| (*t, *v as f64)).collect())
+    });
+
+    SensorGraphData {
+        name: sensor.name.clone(),
+        start,
+        end,
+        motions,
+        temperatures,
+        light_levels,
+        memory_since: Some(since),
+    }
+}
+
+/// Returns the sensor's history over `range`, summarised into about `buckets` buckets. Ranges
+/// reaching back past the raw readings kept are read from rollups, in buckets no finer than them.
 #[server]
-pub async fn get_graph_data(sensor_id: String) -> Result<SensorGraphData, ServerFnError> {
-    let pool = crate::hue::get_db_pool().await?;
+pub async fn get_graph_data(
+    sensor_id: String,
+    range: GraphRange,
+    buckets: usize,
+) -> Result<SensorGraphData, ServerFnError> {
+    use crate::hue::aggregate::bucket_size;
 
     let sensors = crate::hue::get_sensors_cached().await?;
     let sensor = sensors
         .iter()
         .find(|s| s.device_id == sensor_id)
         .ok_or_else(|| ServerFnError::new("Sensor not found"))?;
+    let now = Utc::now();
+    if !crate::hue::storage_configured() {
+        return Ok(memory_graph_data(sensor, range, buckets, now));
+    }
 
-    let end = Utc::now();
-    let start = end - Duration::hours(24);
+    let storage = crate::hue::get_storage().await?;
+    let sources = history_sources(&storage, sensor).await?;
+    check_history_source(sensor, &sources)?;
+
+    let (start, end) = range.bounds(now);
+    let rollup = crate::hue::retention::POLICY.source_for(start, now);
+    let bucket = fit_bucket(bucket_size(start, end, buckets.clamp(1, MAX_BUCKETS)), rollup);
 
     let mut motions = Vec::new();
     let mut temperatures = Vec::new();
     let mut light_levels = Vec::new();
 
-    let extract_id = |id_v1: &Option<String>| {
-        id_v1
-            .as_ref()
-            .and_then(|s| s.split('/').last())
-            .and_then(|s| s.parse::<i32>().ok())
-    };
+    if let Some(m) = &sources.motion {
+        motions = load_motion(&storage, m, start, end, bucket, rollup)
+            .await?
+            .into_iter()
+            .map(|a| GraphPoint {
+                timestamp: a.start,
+                value: a.avg,
+            })
+            .collect();
+    }
 
-    if let Some(m) = &sensor.motion {
-        if let Some(v1_id) = extract_id(&m.id_v1) {
-            let rows = sqlx::query("select creationtime, motion from sensor_motion($1, $2, $3)")
-                .bind(v1_id)
-                .bind(start)
-                .bind(end)
-                .fetch_all(&pool)
-                .await
-                .map_err(|e| ServerFnError::new(e.to_string()))?;
-
-            for row in rows {
-                use sqlx::Row;
-                let creationtime: chrono::NaiveDateTime = row.get(0);
-                let motion: Option<bool> = row.get(1);
-                if let Some(value) = motion {
-                    motions.push(GraphPoint {
-                        timestamp: DateTime::from_naive_utc_and_offset(creationtime, Utc),
-                        value,
-                    });
+    if let Some(t) = &sources.temperature {
+        let offset = sensor.settings.temperature_offset;
+        temperatures = load_aggregates(&storage, t, start, end, bucket, rollup)
+            .await?
+            .into_iter()
+            .map(|a| Aggregate {
+                min: a.min + offset,
+                avg: a.avg + offset,
+                max: a.max + offset,
+                ..a
+            })
+            .collect();
+    }
+
+    if let Some(l) = &sources.light {
+        light_levels = load_aggregates(&storage, l, start, end, bucket, rollup).await?;
+    }
+
+    Ok(SensorGraphData {
+        name: sensor.name.clone(),
+        start,
+        end,
+        motions,
+        temperatures,
+        light_levels,
+        memory_since: None,
+    })
+}
+
+/// One sensor's series in a comparison
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComparisonSeries {
+    pub device_id: String,
+    pub name: String,
+    /// Calibrated temperatures in °C, raw bridge light levels, or the fraction of each bucket
+    /// with motion detected
+    pub points: Vec<Aggregate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComparisonData {
+    pub metric: Metric,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub series: Vec<ComparisonSeries>,
+    /// Names of the chosen sensors without the metric
+    pub missing: Vec<String>,
+}
+
+/// Returns one metric of several sensors over `range`, in the same buckets so they can share axes
+#[server]
+pub async fn get_comparison_data(
+    sensor_ids: Vec<String>,
+    metric: Metric,
+    range: GraphRange,
+    buckets: usize,
+) -> Result<ComparisonData, ServerFnError> {
+    use crate::hue::aggregate::bucket_size;
+
+    let storage = crate::hue::get_storage().await?;
+    let sensors = crate::hue::get_sensors_cached().await?;
+
+    let now = Utc::now();
+    let (start, end) = range.bounds(now);
+    let rollup = crate::hue::retention::POLICY.source_for(start, now);
+    let bucket = fit_bucket(bucket_size(start, end, buckets.clamp(1, MAX_BUCKETS)), rollup);
+
+    let mut series = Vec::new();
+    let mut missing = Vec::new();
+    for id in &sensor_ids {
+        let sensor = sensors
+            .iter()
+            .find(|s| &s.device_id == id)
+            .ok_or_else(|| ServerFnError::new(format!("Sensor {} not found", id)))?;
+        let sources = history_sources(&storage, sensor).await?;
+        let points = match metric {
+            Metric::Motion => match &sources.motion {
+                Some(m) => Some(load_motion(&storage, m, start, end, bucket, rollup).await?),
+                None => None,
+            },
+            Metric::Temperature => match &sources.temperature {
+                Some(t) => {
+                    let offset = sensor.settings.temperature_offset;
+                    let points = load_aggregates(&storage, t, start, end, bucket, rollup).await?;
+                    Some(
+                        points
+                            .into_iter()
+                            .map(|a| Aggregate {
+                                min: a.min + offset,
+                                avg: a.avg + offset,
+                                max: a.max + offset,
+                                ..a
+                            })
+                            .collect(),
+                    )
+                }
+                None => None,
+            },
+            Metric::LightLevel => match &sources.light {
+                Some(l) => Some(load_aggregates(&storage, l, start, end, bucket, rollup).await?),
+                None => None,
+            },
+        };
+        match points {
+            Some(points) => series.push(ComparisonSeries {
+                device_id: sensor.device_id.clone(),
+                name: sensor.name.clone(),
+                points,
+            }),
+            None => missing.push(sensor.name.clone()),
+        }
+    }
+
+    Ok(ComparisonData {
+        metric,
+        start,
+        end,
+        series,
+        missing,
+    })
+}
+
+/// The name of every sensor, for choosing which to export or compare
+#[server]
+pub async fn get_sensor_names() -> Result<Vec<(String, String)>, ServerFnError> {
+    Ok(crate::hue::get_sensors_cached()
+        .await?
+        .into_iter()
+        .map(|s| (s.device_id, s.name))
+        .collect())
+}
+
+/// Buckets read per query while exporting, so memory use stays flat however long the range
+#[cfg(feature = "server")]
+const EXPORT_WINDOW_BUCKETS: i32 = 1000;
+
+/// One metric of one sensor to export
+#[cfg(feature = "server")]
+struct ExportJob {
+    sensor: String,
+    device_id: String,
+    source: HistorySource,
+    metric: Metric,
+    temperature_offset: f64,
+}
+
+#[cfg(feature = "server")]
+impl ExportJob {
+    fn for_sensor(
+        sensor: &crate::hue::client::CompositeSensor,
+        sources: HistorySources,
+    ) -> Vec<Self> {
+        let services = [
+            sources.motion.map(|m| (m, Metric::Motion)),
+            sources.temperature.map(|t| (t, Metric::Temperature)),
+            sources.light.map(|l| (l, Metric::LightLevel)),
+        ];
+        services
+            .into_iter()
+            .flatten()
+            .map(|(source, metric)| Self {
+                sensor: sensor.name.clone(),
+                device_id: sensor.device_id.clone(),
+                source,
+                metric,
+                temperature_offset: sensor.settings.temperature_offset,
+            })
+            .collect()
+    }
+
+    /// The buckets in `from..to`, calibrated and with light levels in lux
+    async fn load(
+        &self,
+        storage: &crate::hue::storage::Storage,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        bucket: chrono::Duration,
+        rollup: Option<chrono::Duration>,
+    ) -> Result<Vec<crate::hue::export::ExportRow>, ServerFnError> {
+        let last = to - chrono::Duration::milliseconds(1);
+        let buckets = match self.metric {
+            Metric::Motion => load_motion(storage, &self.source, from, to, bucket, rollup).await?,
+            Metric::Temperature => read_buckets(storage, &self.source, from, last, bucket, rollup)
+                .await?
+                .into_iter()
+                .map(|a| Aggregate {
+                    min: a.min + self.temperature_offset,
+                    avg: a.avg + self.temperature_offset,
+                    max: a.max + self.temperature_offset,
+                    ..a
+                })
+                .collect(),
+            Metric::LightLevel => read_buckets(storage, &self.source, from, last, bucket, rollup)
+                .await?
+                .into_iter()
+                .map(|a| Aggregate {
+                    min: light_level_to_lux(a.min.round() as i32),
+                    avg: light_level_to_lux(a.avg.round() as i32),
+                    max: light_level_to_lux(a.max.round() as i32),
+                    ..a
+                })
+                .collect(),
+        };
+        Ok(buckets
+            .into_iter()
+            .map(|a| crate::hue::export::ExportRow {
+                sensor: self.sensor.clone(),
+                device_id: self.device_id.clone(),
+                metric: self.metric,
+                time: a.start,
+                min: a.min,
+                avg: a.avg,
+                max: a.max,
+            })
+            .collect())
+    }
+}
+
+/// Streams an export as CSV or a JSON array. Each sensor metric is read a window of buckets at a
+/// time and sent as it is read, ending with an error if a read fails. Ranges reaching past the raw
+/// readings are read from rollups, so buckets may be coarser than asked.
+#[cfg(feature = "server")]
+async fn export_rows(
+    request: ExportRequest,
+) -> Result<impl futures::Stream<Item = Result<String, ServerFnError>>, ServerFnError> {
+    use crate::hue::aggregate::bucket_start;
+    use crate::hue::export::ExportWriter;
+    use std::collections::VecDeque;
+
+    request.time_zone.validate().map_err(ServerFnError::new)?;
+    let storage = crate::hue::get_storage().await?;
+    let sensors = crate::hue::get_sensors_cached().await?;
+    let mut jobs = VecDeque::new();
+    for id in &request.sensor_ids {
+        let sensor = sensors
+            .iter()
+            .find(|s| &s.device_id == id)
+            .ok_or_else(|| ServerFnError::new(format!("Sensor {} not found", id)))?;
+        let sources = history_sources(&storage, sensor).await?;
+        jobs.extend(ExportJob::for_sensor(sensor, sources));
+    }
+
+    let now = Utc::now();
+    let (start, end) = request.range.bounds(now);
+    let rollup = crate::hue::retention::POLICY.source_for(start, now);
+    let minutes = chrono::Duration::minutes(request.bucket_minutes.max(1) as i64);
+    let bucket = fit_bucket(minutes, rollup);
+    let window = bucket * EXPORT_WINDOW_BUCKETS;
+
+    let writer = ExportWriter::new(request.format, request.time_zone, request.temperature_unit);
+    let head = futures::stream::iter([Ok(writer.header())]);
+    let state = (jobs, start, writer, false);
+    let rows = futures::stream::unfold(state, move |(mut jobs, mut from, mut writer, finished)| {
+        let storage = storage.clone();
+        async move {
+            if finished {
+                return None;
+            }
+            loop {
+                if jobs.is_empty() {
+                    let footer = writer.footer();
+                    return Some((Ok(footer), (jobs, from, writer, true)));
+                }
+                if from >= end {
+                    jobs.pop_front();
+                    from = start;
+                    continue;
+                }
+                // Windows after the first are aligned to the buckets, so none is split
+                let to = (bucket_start(from, bucket) + window).min(end);
+                match jobs[0].load(&storage, from, to, bucket, rollup).await {
+                    Ok(rows) => {
+                        from = to;
+                        if !rows.is_empty() {
+                            let chunk = writer.rows(&rows);
+                            return Some((Ok(chunk), (jobs, from, writer, false)));
+                        }
+                    }
+                    Err(e) => {
+                        println!("History export failed: {}", e);
+                        return Some((Err(e), (jobs, from, writer, true)));
+                    }
                 }
             }
         }
+    });
+
+    Ok(head.chain(rows))
+}
+
+/// Serves an export at [`EXPORT_PATH`](crate::hue::export::EXPORT_PATH) as a file download. The
+/// browser fetches it directly and writes it to disk as it arrives; a read failing part-way aborts
+/// the response, so the download fails rather than saving a truncated file.
+#[cfg(feature = "server")]
+pub async fn export_download(
+    dioxus::fullstack::axum::extract::RawQuery(query): dioxus::fullstack::axum::extract::RawQuery,
+) -> dioxus::fullstack::axum::response::Response {
+    use dioxus::fullstack::axum::{body::Body, response::IntoResponse};
+    use dioxus::fullstack::http::{header, StatusCode};
+    use futures::StreamExt;
+
+    let request = match ExportRequest::from_query(query.as_deref().unwrap_or_default()) {
+        Ok(request) => request,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let name = request.file_name(Utc::now());
+    let mime_type = request.format.mime_type();
+    match export_rows(request).await {
+        Ok(rows) => {
+            let body = Body::from_stream(
+                rows.map(|chunk| chunk.map_err(|e| std::io::Error::other(e.to_string()))),
+            );
+            let headers = [
+                (header::CONTENT_TYPE, mime_type.to_string()),
+                (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name)),
+            ];
+            (headers, body).into_response()
+        }
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     }
+}
+
+/// Has the browser download an export from [`EXPORT_PATH`](crate::hue::export::EXPORT_PATH), so it
+/// never holds the whole file
+#[cfg(feature = "web")]
+fn start_download(request: &ExportRequest) -> Result<(), String> {
+    use web_sys::wasm_bindgen::JsCast;
+
+    let link = web_sys::window()
+        .and_then(|w| w.document())
+        .and_then(|d| d.create_element("a").ok())
+        .and_then(|e| e.dyn_into::<web_sys::HtmlAnchorElement>().ok())
+        .ok_or("Unable to start the download")?;
+    link.set_href(&request.url());
+    link.set_download(&request.file_name(Utc::now()));
+    link.click();
+    Ok(())
+}
+
+#[cfg(not(feature = "web"))]
+fn start_download(_request: &ExportRequest) -> Result<(), String> {
+    Err("Downloads are only available in the browser".to_string())
+}
+
+/// Bucket sizes offered for exports, in minutes
+const EXPORT_BUCKETS: [(u32, &str); 4] = [
+    (5, "5 minutes"),
+    (15, "15 minutes"),
+    (60, "1 hour"),
+    (1440, "1 day"),
+];
 
-    if let Some(t) = &sensor.temperature {
-        if let Some(v1_id) = extract_id(&t.id_v1) {
-            let rows =
-                sqlx::query("select creationtime, temperature from sensor_temperature($1, $2, $3)")
-                    .bind(v1_id)
-                    .bind(start)
-                    .bind(end)
-                    .fetch_all(&pool)
-                    .await
-                    .map_err(|e| ServerFnError::new(e.to_string()))?;
-
-            for row in rows {
-                use sqlx::Row;
-                let creationtime: chrono::NaiveDateTime = row.get(0);
-                let temperature: Option<f32> = row.get(1);
-                if let Some(value) = temperature {
-                    temperatures.push(GraphPoint {
-                        timestamp: DateTime::from_naive_utc_and_offset(creationtime, Utc),
-                        value,
-                    });
+/// Exports the graphed range for this and any other chosen sensors
+#[component]
+fn ExportPanel(sensor_id: String, range: GraphRange) -> Element {
+    let prefs = use_preferences()();
+    let sensors = use_resource(get_sensor_names);
+    let mut selected = use_signal(move || vec![sensor_id.clone()]);
+    let mut format = use_signal(ExportFormat::default);
+    let mut bucket_minutes = use_signal(|| EXPORT_BUCKETS[0].0);
+    let mut error = use_signal(|| None::<String>);
+
+    let export = move |_: Event<MouseData>| {
+        let request = ExportRequest {
+            sensor_ids: selected(),
+            range,
+            bucket_minutes: bucket_minutes(),
+            format: format(),
+            time_zone: prefs.export_time_zone(),
+            temperature_unit: prefs.temperature_unit,
+        };
+        // Check the zone here, as the browser shows a failed download without the reason
+        error.set(
+            request
+                .time_zone
+                .validate()
+                .and_then(|()| start_download(&request))
+                .err(),
+        );
+    };
+
+    let choice_class = |active: bool| {
+        if active {
+            "px-3 py-1 rounded bg-blue-600 text-white text-sm"
+        } else {
+            "px-3 py-1 rounded bg-gray-100 dark:bg-gray-700 hover:bg-gray-200 dark:hover:bg-gray-600 text-sm"
+        }
+    };
+
+    rsx! {
+        div { class: "flex flex-col gap-4",
+            div { class: "flex flex-wrap gap-x-4 gap-y-2",
+                {
+                    match &*sensors.read() {
+                        Some(Ok(names)) => rsx! {
+                            for (device_id, name) in names.clone() {
+                                label { class: "flex items-center gap-2 text-sm",
+                                    input {
+                                        r#type: "checkbox",
+                                        checked: selected.read().contains(&device_id),
+                                        onchange: move |e| {
+                                            let mut ids = selected.write();
+                                            ids.retain(|id| id != &device_id);
+                                            if e.checked() {
+                                                ids.push(device_id.clone());
+                                            }
+                                        },
+                                    }
+                                    "{name}"
+                                }
+                            }
+                        },
+                        Some(Err(e)) => rsx! { span { class: "text-sm text-red-600", "{e}" } },
+                        None => rsx! { span { class: "text-sm text-gray-400 italic", "Loading sensors…" } },
+                    }
+                }
+            }
+            div { class: "flex flex-wrap items-center gap-2",
+                for (minutes, label) in EXPORT_BUCKETS {
+                    button {
+                        class: choice_class(bucket_minutes() == minutes),
+                        onclick: move |_| bucket_minutes.set(minutes),
+                        "{label}"
+                    }
+                }
+                span { class: "w-4" }
+                for (value, label) in [(ExportFormat::Csv, "CSV"), (ExportFormat::Json, "JSON")] {
+                    button {
+                        class: choice_class(format() == value),
+                        onclick: move |_| format.set(value),
+                        "{label}"
+                    }
                 }
+                button {
+                    class: "ml-auto px-4 py-1 rounded bg-green-600 hover:bg-green-700 text-white text-sm disabled:opacity-50",
+                    disabled: selected.read().is_empty(),
+                    onclick: export,
+                    "Download"
+                }
+            }
+            if let Some(e) = error() {
+                span { class: "text-sm text-red-600", "{e}" }
             }
         }
     }
+}
 
-    if let Some(l) = &sensor.light {
-        if let Some(v1_id) = extract_id(&l.id_v1) {
-            let rows =
-                sqlx::query("select creationtime, light_level from sensor_light_level($1, $2, $3)")
-                    .bind(v1_id)
-                    .bind(start)
-                    .bind(end)
-                    .fetch_all(&pool)
-                    .await
-                    .map_err(|e| ServerFnError::new(e.to_string()))?;
-
-            for row in rows {
-                use sqlx::Row;
-                let creationtime: chrono::NaiveDateTime = row.get(0);
-                let light_level: Option<i32> = row.get(1);
-                if let Some(value) = light_level {
-                    light_levels.push(GraphPoint {
-                        timestamp: DateTime::from_naive_utc_and_offset(creationtime, Utc),
-                        value,
-                    });
+#[component]
+pub fn Graphs(sensor_id: String, range: GraphRange) -> Element {
+    let device_id = sensor_id.clone();
+
+    rsx! {
+        div {
+            class: "w-full p-4 pb-20 max-w-[100vw] overflow-x-hidden",
+            div { class: "max-w-7xl mx-auto",
+                ErrorBoundary {
+                    handle_error: |errors: ErrorContext| {
+                        let message = errors.error().map(|e| e.to_string()).unwrap_or_default();
+                        rsx! {
+                            div { class: "flex items-center gap-4 mb-6",
+                                BackLink {}
+                                h1 { class: "text-2xl font-bold", "Sensor Graphs" }
+                            }
+                            div { class: "p-4 rounded-lg border border-amber-300 bg-amber-50 dark:bg-amber-900/30 dark:border-amber-700 text-amber-800 dark:text-amber-200",
+                                h2 { class: "font-semibold mb-1", "No history to show" }
+                                p { class: "text-sm", "{message}" }
+                            }
+                        }
+                    },
+                    SensorGraphs { sensor_id, range }
+                }
+                div {
+                    class: "p-4 mt-8 bg-white dark:bg-gray-800 rounded-lg shadow w-full max-w-xl",
+                    h2 { class: "text-lg font-semibold mb-4", "Settings" }
+                    SensorSettingsForm { device_id }
                 }
             }
         }
     }
+}
 
-    Ok(SensorGraphData {
-        name: sensor.name.clone(),
-        motions,
-        temperatures,
-        light_levels,
-    })
+#[component]
+fn BackLink() -> Element {
+    rsx! {
+        Link {
+            to: Route::Sensors {},
+            class: "p-2 rounded-full hover:bg-gray-100 dark:hover:bg-gray-800 transition-colors",
+            "←"
+        }
+    }
 }
 
+/// The range picker and the sensor's graphs. Fails with the reason if the sensor has no history.
 #[component]
-pub fn Graphs(sensor_id: String) -> Element {
-    let data = use_loader(move || get_graph_data(sensor_id.clone()))?;
+fn SensorGraphs(sensor_id: String, range: GraphRange) -> Element {
+    let mut light_log_scale = use_signal(|| true);
+    let prefs = use_preferences()();
+    let picker_id = sensor_id.clone();
+    let export_id = sensor_id.clone();
+    let stats_id = sensor_id.clone();
+    let data = use_loader(use_reactive!(|(sensor_id, range)| get_graph_data(
+        sensor_id,
+        range,
+        (CHART_WIDTH / 2) as usize
+    )))?;
     let data = data.read();
 
     let motion_history = data
         .motions
         .iter()
         .map(|p| HistoryPoint {
-            value: if p.value { 1.0 } else { 0.0 },
+            value: p.value,
             time: p.timestamp,
         })
         .collect::<Vec<_>>();
@@ -140,68 +913,130 @@ pub fn Graphs(sensor_id: String) -> Element {
     let temp_history = data
         .temperatures
         .iter()
-        .map(|p| HistoryPoint {
-            value: p.value as f64,
-            time: p.timestamp,
+        .map(|a| HistoryPoint {
+            value: prefs.convert_temperature(a.avg),
+            time: a.start,
         })
         .collect::<Vec<_>>();
+    let temp_band = data
+        .temperatures
+        .iter()
+        .map(|a| (prefs.convert_temperature(a.min), prefs.convert_temperature(a.max)))
+        .collect::<Vec<_>>();
 
+    // Light levels are logarithmic, so the average is taken before converting to lux
     let light_history = data
         .light_levels
         .iter()
-        .map(|p| HistoryPoint {
-            value: p.value as f64,
-            time: p.timestamp,
+        .map(|a| HistoryPoint {
+            value: light_level_to_lux(a.avg.round() as i32),
+            time: a.start,
+        })
+        .collect::<Vec<_>>();
+    let light_band = data
+        .light_levels
+        .iter()
+        .map(|a| {
+            (
+                light_level_to_lux(a.min.round() as i32),
+                light_level_to_lux(a.max.round() as i32),
+            )
         })
         .collect::<Vec<_>>();
 
     rsx! {
+        div { class: "flex items-center gap-4 mb-6",
+            BackLink {}
+            h1 { class: "text-2xl font-bold", "Sensor Graphs for {data.name}" }
+            // Stats read from the database
+            if data.memory_since.is_none() {
+                Link {
+                    to: Route::Stats { sensor_id: stats_id },
+                    class: "ml-auto px-3 py-1 rounded bg-gray-100 dark:bg-gray-800 hover:bg-gray-200 dark:hover:bg-gray-700 text-sm",
+                    "Daily stats"
+                }
+            }
+        }
+        RangePicker {
+            key: "{range}",
+            range,
+            to: move |range| Route::Graphs {
+                sensor_id: picker_id.clone(),
+                range,
+            },
+        }
+        if let Some(since) = data.memory_since {
+            div { class: "p-4 mb-6 rounded-lg border border-amber-300 bg-amber-50 dark:bg-amber-900/30 dark:border-amber-700 text-amber-800 dark:text-amber-200",
+                h2 { class: "font-semibold mb-1", "Showing recent history only" }
+                p { class: "text-sm",
+                    "No database is configured, so graphs only cover the history held in memory, from {prefs.format_datetime(since)}. Set DATABASE_URL to keep longer history."
+                }
+            }
+        }
         div {
-            class: "w-full p-4 pb-20 max-w-[100vw] overflow-x-hidden",
-            div { class: "max-w-7xl mx-auto",
-                div { class: "flex items-center gap-4 mb-6",
-                    Link {
-                        to: crate::Route::Sensors {},
-                        class: "p-2 rounded-full hover:bg-gray-100 dark:hover:bg-gray-800 transition-colors",
-                        "←"
+            class: "grid grid-cols-1 gap-8",
+            div {
+                class: "p-4 bg-white dark:bg-gray-800 rounded-lg shadow w-full",
+                h2 { class: "text-lg font-semibold mb-2", "Motion" }
+                div { class: "h-64 w-full",
+                    SensorDataGraph {
+                        history: motion_history,
+                        start: data.start,
+                        end: data.end,
+                        width: CHART_WIDTH,
+                        is_discrete: true,
+                        color: "#f87171" // red-400
                     }
-                    h1 { class: "text-2xl font-bold", "Sensor Graphs for {data.name}" }
                 }
-                div {
-                    class: "grid grid-cols-1 gap-8",
-                    div {
-                        class: "p-4 bg-white dark:bg-gray-800 rounded-lg shadow w-full",
-                        h2 { class: "text-lg font-semibold mb-2", "Motion" }
-                        div { class: "h-64 w-full",
-                            SensorDataGraph {
-                                history: motion_history,
-                                is_discrete: true,
-                                color: "#f87171" // red-400
-                            }
+            }
+            div {
+                class: "p-4 bg-white dark:bg-gray-800 rounded-lg shadow w-full",
+                h2 { class: "text-lg font-semibold mb-2", "Temperature" }
+                div { class: "h-64 w-full",
+                    SensorDataGraph {
+                        history: temp_history,
+                        band: temp_band,
+                        start: data.start,
+                        end: data.end,
+                        width: CHART_WIDTH,
+                        unit: prefs.temperature_unit.symbol().to_string(),
+                        color: "#60a5fa" // blue-400
+                    }
+                }
+            }
+            div {
+                class: "p-4 bg-white dark:bg-gray-800 rounded-lg shadow w-full",
+                div { class: "flex items-center justify-between mb-2",
+                    h2 { class: "text-lg font-semibold", "Light Level" }
+                    label { class: "flex items-center gap-2 text-sm text-gray-600 dark:text-gray-400",
+                        input {
+                            r#type: "checkbox",
+                            checked: light_log_scale(),
+                            onchange: move |e| light_log_scale.set(e.checked()),
                         }
+                        "Log scale"
                     }
-                                    div {
-                                        class: "p-4 bg-white dark:bg-gray-800 rounded-lg shadow w-full",
-                                        h2 { class: "text-lg font-semibold mb-2", "Temperature" }
-                                        div { class: "h-64 w-full",
-                                            SensorDataGraph {
-                                                history: temp_history,
-                                                unit: "°C".to_string(),
-                                                color: "#60a5fa" // blue-400
-                                            }
-                                        }
-                                    }
-                                    div {
-                                        class: "p-4 bg-white dark:bg-gray-800 rounded-lg shadow w-full",
-                                        h2 { class: "text-lg font-semibold mb-2", "Light Level" }
-                                        div { class: "h-64 w-full",
-                                            SensorDataGraph {
-                                                history: light_history,
-                                                unit: "lx".to_string(),
-                                                color: "#fbbf24" // amber-400
-                                            }
-                                        }
-                                    }                }
+                }
+                div { class: "h-64 w-full",
+                    SensorDataGraph {
+                        history: light_history,
+                        band: light_band,
+                        start: data.start,
+                        end: data.end,
+                        width: CHART_WIDTH,
+                        unit: "lx".to_string(),
+                        log_scale: light_log_scale(),
+                        color: "#fbbf24" // amber-400
+                    }
+                }
+            }
+            // Exports read from the database
+            if data.memory_since.is_none() {
+                div {
+                    class: "p-4 bg-white dark:bg-gray-800 rounded-lg shadow w-full",
+                    h2 { class: "text-lg font-semibold mb-4", "Export" }
+                    ExportPanel { sensor_id: export_id, range }
+                }
             }
         }
     }
@@ -0,0 +1,188 @@
+use crate::hue::import::{parse, ImportReport, ImportRow, IMPORT_BATCH};
+use dioxus::prelude::*;
+
+/// Writes a batch of rows parsed from an import file to the history tables. Batches may hold at
+/// most [`IMPORT_BATCH`] rows, and rows failing the parser's checks are rejected.
+#[server]
+pub async fn import_readings(rows: Vec<ImportRow>) -> Result<ImportReport, ServerFnError> {
+    if rows.len() > IMPORT_BATCH {
+        return Err(ServerFnError::new(format!(
+            "Batches may hold at most {} rows, not {}",
+            IMPORT_BATCH,
+            rows.len()
+        )));
+    }
+    let storage = crate::hue::get_storage().await?;
+    let sensors = crate::hue::get_sensors_cached().await?;
+    crate::hue::import::import_batch(&storage, &sensors, &rows)
+        .await
+        .map_err(|e| ServerFnError::new(e.to_string()))
+}
+
+/// An import in progress or finished
+#[derive(Debug, Clone, PartialEq)]
+struct ImportState {
+    file: String,
+    /// Valid rows sent to the server so far, out of `total`
+    sent: usize,
+    total: usize,
+    report: ImportReport,
+    error: Option<String>,
+    finished: bool,
+}
+
+/// The Import page component that will be rendered when the current route is `[Route::Import]`.
+/// Files are parsed and checked in the browser, then sent a batch at a time so progress can be
+/// shown and no request grows too large.
+#[component]
+pub fn Import() -> Element {
+    let mut state = use_signal(|| None::<ImportState>);
+    let running = state.read().as_ref().is_some_and(|s| !s.finished);
+
+    let upload = move |e: Event<FormData>| {
+        let Some(file) = e.files().into_iter().next() else {
+            return;
+        };
+        spawn(async move {
+            let name = file.name();
+            let text = match file.read_string().await {
+                Ok(text) => text,
+                Err(e) => {
+                    state.set(Some(ImportState {
+                        file: name,
+                        sent: 0,
+                        total: 0,
+                        report: ImportReport::default(),
+                        error: Some(format!("Unable to read the file: {}", e)),
+                        finished: true,
+                    }));
+                    return;
+                }
+            };
+            let (rows, report) = parse(&text, chrono::Utc::now());
+            drop(text);
+            state.set(Some(ImportState {
+                file: name,
+                sent: 0,
+                total: rows.len(),
+                report,
+                error: None,
+                finished: false,
+            }));
+
+            for batch in rows.chunks(IMPORT_BATCH) {
+                let result = import_readings(batch.to_vec()).await;
+                let mut state = state.write();
+                let Some(state) = state.as_mut() else { return };
+                match result {
+                    Ok(report) => {
+                        state.report.merge(report);
+                        state.sent += batch.len();
+                    }
+                    Err(e) => {
+                        state.error = Some(e.to_string());
+                        break;
+                    }
+                }
+            }
+            if let Some(state) = state.write().as_mut() {
+                state.finished = true;
+            }
+        });
+    };
+
+    rsx! {
+        div { class: "container mx-auto p-4 max-w-3xl",
+            h1 { class: "text-2xl font-bold mb-2", "Import history" }
+            p { class: "text-sm text-gray-600 dark:text-gray-400 mb-6",
+                "Load readings from a CSV file with timestamp, device, metric and value columns, or a JSON file of
+                objects with those fields or v1 logger entries. Devices are matched to sensors by id, v1 sensor
+                number or name; rows already recorded are skipped."
+            }
+            div { class: "p-4 bg-white dark:bg-gray-800 rounded-lg shadow mb-6",
+                input {
+                    r#type: "file",
+                    accept: ".csv,.json,.ndjson,.jsonl,text/csv,application/json",
+                    disabled: running,
+                    onchange: upload,
+                }
+            }
+            if let Some(import) = state() {
+                ImportProgress { import }
+            }
+        }
+    }
+}
+
+#[component]
+fn ImportProgress(import: ImportState) -> Element {
+    let report = &import.report;
+    let percent = if import.total == 0 {
+        100
+    } else {
+        import.sent * 100 / import.total
+    };
+    let status = match (&import.error, import.finished) {
+        (Some(_), _) => "Stopped",
+        (None, true) => "Finished",
+        (None, false) => "Importing…",
+    };
+    let unlisted = report.rejected.saturating_sub(report.errors.len());
+
+    rsx! {
+        div { class: "p-4 bg-white dark:bg-gray-800 rounded-lg shadow space-y-4",
+            div { class: "flex justify-between items-baseline",
+                h2 { class: "text-lg font-semibold", "{import.file}" }
+                span { class: "text-sm text-gray-500", "{status}" }
+            }
+            div { class: "w-full h-2 rounded bg-gray-200 dark:bg-gray-700 overflow-hidden",
+                div {
+                    class: "h-full bg-blue-600 transition-all",
+                    width: "{percent}%",
+                }
+            }
+            div { class: "grid grid-cols-2 sm:grid-cols-4 gap-4 text-sm",
+                div {
+                    div { class: "text-gray-500", "Rows read" }
+                    div { class: "text-lg font-semibold", "{report.rows}" }
+                }
+                div {
+                    div { class: "text-gray-500", "Imported" }
+                    div { class: "text-lg font-semibold text-green-600", "{report.imported}" }
+                }
+                div {
+                    div { class: "text-gray-500", "Duplicates" }
+                    div { class: "text-lg font-semibold", "{report.duplicates}" }
+                }
+                div {
+                    div { class: "text-gray-500", "Rejected" }
+                    div { class: "text-lg font-semibold text-red-600", "{report.rejected}" }
+                }
+            }
+            if let Some(error) = &import.error {
+                p { class: "text-sm text-red-600", "The import stopped after {import.sent} of {import.total} rows: {error}" }
+            }
+            if !report.errors.is_empty() {
+                table { class: "w-full text-sm",
+                    thead {
+                        tr { class: "text-left text-gray-500",
+                            th { class: "pr-4 font-normal", "Line" }
+                            th { class: "font-normal", "Error" }
+                        }
+                    }
+                    tbody {
+                        for error in report.errors.iter() {
+                            tr { class: "border-t border-gray-100 dark:border-gray-700",
+                                td { class: "pr-4 py-1 font-mono", "{error.line}" }
+                                td { class: "py-1", "{error.message}" }
+                            }
+                        }
+                    }
+                }
+                if unlisted > 0 {
+                    p { class: "text-sm text-gray-500", "…and {unlisted} more" }
+                }
+            }
+        }
+    }
+}
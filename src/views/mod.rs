@@ -22,3 +22,17 @@ pub use event_log::EventLog;
 
 mod graphs;
 pub use graphs::Graphs;
+#[cfg(feature = "server")]
+pub(crate) use graphs::{export_download, memory_graph_data};
+
+mod compare;
+pub use compare::Compare;
+
+mod stats;
+pub use stats::Stats;
+
+mod settings;
+pub use settings::Settings;
+
+mod import;
+pub use import::Import;
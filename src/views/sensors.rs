@@ -1,48 +1,39 @@
 use crate::components::{ActivityIndicator, Clock, Sensor};
-use crate::hue::client::CompositeSensor;
+use crate::hue::client::{group_by_room, CompositeSensor, GroupSummary, SensorGroup};
+use crate::hue::get_sensors;
+use crate::hue::index::SensorIndex;
+use crate::preferences::use_preferences;
 use chrono::Utc;
 use dioxus::prelude::*;
 
-#[server]
-async fn get_sensors() -> Result<Vec<CompositeSensor>, ServerFnError> {
-    crate::hue::get_sensors_cached().await
-}
-
 /// The Sensors page component that will be rendered when the current route is `[Route::Sensors]`
 #[component]
 pub fn Sensors() -> Element {
     let initial_sensors = use_loader(get_sensors)?;
     let mut sensors = use_signal(move || initial_sensors.read().clone());
+    let mut index = use_signal(move || SensorIndex::new(&sensors.peek()));
     let mut last_global_update = use_signal(Utc::now);
 
-    crate::hue::use_hue_event_handler(
-        false,
-        move |event| {
-            let owner_rid = event.owner_rid();
-            let resource_id = event.resource_id();
-
-            let mut updated = false;
-            sensors.with_mut(|list: &mut Vec<CompositeSensor>| {
-                for s in list.iter_mut() {
-                    let is_owner = owner_rid == Some(s.device_id.as_str());
-                    let matches_resource = resource_id.is_some()
-                        && (s.motion.as_ref().map(|m| m.id.as_str()) == resource_id
-                            || s.temperature.as_ref().map(|t| t.id.as_str()) == resource_id
-                            || s.light.as_ref().map(|l| l.id.as_str()) == resource_id);
-
-                    if is_owner || matches_resource {
-                        s.apply_event(&event);
-                        updated = true;
-                    }
-                }
+    // The server sends each change already merged into its sensor cache, so applying it here keeps
+    // this list identical to the cache
+    crate::hue::use_sensor_updates(
+        move |update| {
+            let updated = sensors.with_mut(|list: &mut Vec<CompositeSensor>| {
+                index.with_mut(|index| index.apply_update(list, &update)).is_some()
             });
 
             if updated {
                 last_global_update.set(Utc::now());
             }
         },
-        move |msg| {
-            println!("Error connecting to event stream: {}", msg);
+        move || {
+            spawn(async move {
+                if let Ok(list) = get_sensors().await {
+                    index.set(SensorIndex::new(&list));
+                    sensors.set(list);
+                    last_global_update.set(Utc::now());
+                }
+            });
         },
     );
 
@@ -55,9 +46,68 @@ pub fn Sensors() -> Element {
                     Clock {}
                 }
             }
-            div { class: "grid grid-cols-1 md:grid-cols-2 lg:grid-cols-3 gap-4",
-                for sensor in sensors.read().iter() {
-                    Sensor { key: "{sensor.device_id}", sensor: sensor.clone() }
+            div { class: "space-y-6",
+                for (room, members) in group_by_room(&sensors.read()) {
+                    RoomSection {
+                        key: "{room.as_ref().map(|r| r.id.as_str()).unwrap_or_default()}",
+                        room,
+                        sensors: members,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A collapsible heading summarising a room, followed by its sensor cards
+#[component]
+fn RoomSection(room: Option<SensorGroup>, sensors: Vec<CompositeSensor>) -> Element {
+    let mut prefs = use_preferences();
+    let room_id = room.as_ref().map(|r| r.id.clone()).unwrap_or_default();
+    let name = room.as_ref().map(|r| r.name.as_str()).unwrap_or("No room");
+    let collapsed = prefs.read().collapsed_rooms.contains(&room_id);
+
+    let summary = GroupSummary::new(&sensors);
+    let occupancy = match (summary.motion_sensors, summary.occupied) {
+        (0, _) => None,
+        (_, 0) => Some("Clear".to_string()),
+        (1, _) => Some("Occupied".to_string()),
+        (total, occupied) => Some(format!("Occupied ({occupied}/{total})")),
+    };
+    let occupancy_class = if summary.occupied > 0 {
+        "text-red-600 dark:text-red-400 font-bold"
+    } else {
+        "text-green-600 dark:text-green-400"
+    };
+    let temperature = summary
+        .avg_temperature
+        .map(|t| prefs.read().format_temperature(t));
+
+    rsx! {
+        section {
+            button {
+                class: "w-full flex items-center gap-3 mb-3 pb-1 border-b border-gray-300 dark:border-gray-700 text-left",
+                onclick: move |_| {
+                    let room_id = room_id.clone();
+                    prefs.with_mut(|p| {
+                        p.toggle_room(&room_id);
+                        p.save();
+                    });
+                },
+                span { class: "text-gray-500 w-4", if collapsed { "▸" } else { "▾" } }
+                h2 { class: "text-lg font-semibold flex-grow", "{name}" }
+                if let Some(occupancy) = occupancy {
+                    span { class: "text-sm {occupancy_class}", "{occupancy}" }
+                }
+                if let Some(temperature) = temperature {
+                    span { class: "text-sm text-gray-600 dark:text-gray-400", "{temperature}" }
+                }
+            }
+            if !collapsed {
+                div { class: "grid grid-cols-1 md:grid-cols-2 lg:grid-cols-3 gap-4",
+                    for sensor in sensors {
+                        Sensor { key: "{sensor.device_id}", sensor }
+                    }
                 }
             }
         }
@@ -0,0 +1,108 @@
+use crate::components::Clock;
+use crate::preferences::{use_preferences, DateFormat, HourFormat, Preferences, TemperatureUnit};
+use dioxus::prelude::*;
+
+/// Applies a change to the preferences signal and persists the result
+fn update_preferences(prefs: &mut Signal<Preferences>, f: impl FnOnce(&mut Preferences)) {
+    prefs.with_mut(|p| {
+        f(p);
+        p.save();
+    });
+}
+
+/// The Settings page component that will be rendered when the current route is `[Route::Settings]`
+#[component]
+pub fn Settings() -> Element {
+    let mut prefs = use_preferences();
+    let current = prefs.read().clone();
+
+    let temperature_unit = match current.temperature_unit {
+        TemperatureUnit::Celsius => "c",
+        TemperatureUnit::Fahrenheit => "f",
+    };
+    let hour_format = match current.hour_format {
+        HourFormat::H24 => "24",
+        HourFormat::H12 => "12",
+    };
+    let date_format = match current.date_format {
+        DateFormat::Iso => "iso",
+        DateFormat::DayMonthYear => "dmy",
+        DateFormat::MonthDayYear => "mdy",
+    };
+    let time_zone = current.time_zone.clone().unwrap_or_default();
+    let now = chrono::Utc::now();
+
+    rsx! {
+        div { class: "container mx-auto p-4 max-w-xl",
+            div { class: "flex justify-between items-baseline mb-6",
+                h1 { class: "text-2xl font-bold", "Settings" }
+                Clock {}
+            }
+            div { class: "space-y-4 p-4 bg-white dark:bg-gray-800 rounded-lg shadow",
+                label { class: "grid grid-cols-[10rem_1fr] items-center gap-4",
+                    span { class: "text-gray-600 dark:text-gray-400", "Temperature" }
+                    select {
+                        class: "p-1 rounded border border-gray-300 dark:border-gray-600 dark:bg-gray-900",
+                        value: "{temperature_unit}",
+                        onchange: move |e| {
+                            let unit = if e.value() == "f" { TemperatureUnit::Fahrenheit } else { TemperatureUnit::Celsius };
+                            update_preferences(&mut prefs, |p| p.temperature_unit = unit);
+                        },
+                        option { value: "c", selected: temperature_unit == "c", "Celsius (°C)" }
+                        option { value: "f", selected: temperature_unit == "f", "Fahrenheit (°F)" }
+                    }
+                }
+                label { class: "grid grid-cols-[10rem_1fr] items-center gap-4",
+                    span { class: "text-gray-600 dark:text-gray-400", "Time format" }
+                    select {
+                        class: "p-1 rounded border border-gray-300 dark:border-gray-600 dark:bg-gray-900",
+                        value: "{hour_format}",
+                        onchange: move |e| {
+                            let format = if e.value() == "12" { HourFormat::H12 } else { HourFormat::H24 };
+                            update_preferences(&mut prefs, |p| p.hour_format = format);
+                        },
+                        option { value: "24", selected: hour_format == "24", "24-hour" }
+                        option { value: "12", selected: hour_format == "12", "12-hour" }
+                    }
+                }
+                label { class: "grid grid-cols-[10rem_1fr] items-center gap-4",
+                    span { class: "text-gray-600 dark:text-gray-400", "Date format" }
+                    select {
+                        class: "p-1 rounded border border-gray-300 dark:border-gray-600 dark:bg-gray-900",
+                        value: "{date_format}",
+                        onchange: move |e| {
+                            let format = match e.value().as_str() {
+                                "dmy" => DateFormat::DayMonthYear,
+                                "mdy" => DateFormat::MonthDayYear,
+                                _ => DateFormat::Iso,
+                            };
+                            update_preferences(&mut prefs, |p| p.date_format = format);
+                        },
+                        option { value: "iso", selected: date_format == "iso", "YYYY-MM-DD" }
+                        option { value: "dmy", selected: date_format == "dmy", "DD/MM/YYYY" }
+                        option { value: "mdy", selected: date_format == "mdy", "MM/DD/YYYY" }
+                    }
+                }
+                label { class: "grid grid-cols-[10rem_1fr] items-center gap-4",
+                    span { class: "text-gray-600 dark:text-gray-400", "Time zone" }
+                    select {
+                        class: "p-1 rounded border border-gray-300 dark:border-gray-600 dark:bg-gray-900",
+                        value: "{time_zone}",
+                        onchange: move |e| {
+                            let value = e.value();
+                            let tz = if value.is_empty() { None } else { Some(value) };
+                            update_preferences(&mut prefs, |p| p.time_zone = tz);
+                        },
+                        option { value: "", selected: time_zone.is_empty(), "Browser local time" }
+                        for tz in chrono_tz::TZ_VARIANTS.iter() {
+                            option { value: "{tz.name()}", selected: time_zone == tz.name(), "{tz.name()}" }
+                        }
+                    }
+                }
+                p { class: "text-sm text-gray-500 pt-2 border-t border-gray-200 dark:border-gray-700",
+                    "Preview: {current.format_datetime(now)} · {current.format_temperature(21.5)}"
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use crate::hue::export::ExportTimeZone;
+use crate::hue::stats::{DailyStats, RecordKind, SensorStats, StatRecord};
+use crate::preferences::{use_preferences, Preferences};
+use crate::Route;
+use dioxus::prelude::*;
+
+/// Longest span of days summarised at once
+const MAX_STATS_DAYS: u32 = 366;
+const STATS_DAYS: [(u32, &str); 4] = [(7, "7 days"), (30, "30 days"), (90, "90 days"), (365, "1 year")];
+
+/// How long computed statistics are reused. Past days rarely change, so this only delays today's
+/// figures.
+#[cfg(feature = "server")]
+const STATS_CACHE_MINUTES: i64 = 15;
+
+/// The sensor, days and time zone asked for, and the bits of the temperature offset applied, so a new
+/// calibration isn't served from the cache
+#[cfg(feature = "server")]
+type StatsKey = (String, u32, ExportTimeZone, u64);
+
+#[cfg(feature = "server")]
+static STATS_CACHE: std::sync::LazyLock<
+    tokio::sync::Mutex<std::collections::HashMap<StatsKey, (chrono::DateTime<chrono::Utc>, SensorStats)>>,
+> = std::sync::LazyLock::new(Default::default);
+
+/// Summarises each of the sensor's last `days` local days in `time_zone`, with records for that
+/// span and the latest winter. Results are cached for a few minutes.
+#[server]
+pub async fn get_sensor_stats(
+    sensor_id: String,
+    days: u32,
+    time_zone: ExportTimeZone,
+) -> Result<SensorStats, ServerFnError> {
+    use super::graphs::{
+        check_history_source, fit_bucket, history_sources, load_aggregates, load_motion,
+    };
+    use crate::hue::aggregate::Aggregate;
+    use crate::hue::stats::{compute, winter_start, StatsInput};
+    use chrono::{Duration, Utc};
+
+    time_zone.validate().map_err(ServerFnError::new)?;
+    let days = days.clamp(1, MAX_STATS_DAYS);
+    let now = Utc::now();
+    let sensors = crate::hue::get_sensors_cached().await?;
+    let sensor = sensors
+        .iter()
+        .find(|s| s.device_id == sensor_id)
+        .ok_or_else(|| ServerFnError::new("Sensor not found"))?;
+    let key = (
+        sensor_id.clone(),
+        days,
+        time_zone.clone(),
+        sensor.settings.temperature_offset.to_bits(),
+    );
+    {
+        let mut cache = STATS_CACHE.lock().await;
+        cache.retain(|_, (at, _)| now - *at < Duration::minutes(STATS_CACHE_MINUTES));
+        if let Some((_, stats)) = cache.get(&key) {
+            return Ok(stats.clone());
+        }
+    }
+
+    let storage = crate::hue::get_storage().await?;
+    let sources = history_sources(&storage, sensor).await?;
+    check_history_source(sensor, &sources)?;
+
+    let today = time_zone.localize(now).date_naive();
+    let first_day = today - Duration::days(days as i64 - 1);
+    let start = time_zone
+        .midnight(first_day.min(winter_start(today)))
+        .unwrap_or(now - Duration::days(days as i64));
+    let rollup = crate::hue::retention::POLICY.source_for(start, now);
+    let bucket = fit_bucket(Duration::hours(1), rollup);
+    let raw_since = crate::hue::retention::POLICY
+        .raw
+        .map_or(start, |keep| (now - keep).max(start));
+
+    let mut input = StatsInput {
+        temperatures: Vec::new(),
+        light_levels: Vec::new(),
+        occupancy: Vec::new(),
+        motions: Vec::new(),
+        bucket,
+    };
+    if let Some(t) = &sources.temperature {
+        let offset = sensor.settings.temperature_offset;
+        input.temperatures = load_aggregates(&storage, t, start, now, bucket, rollup)
+            .await?
+            .into_iter()
+            .map(|a| Aggregate {
+                min: a.min + offset,
+                avg: a.avg + offset,
+                max: a.max + offset,
+                ..a
+            })
+            .collect();
+    }
+    if let Some(l) = &sources.light {
+        input.light_levels = load_aggregates(&storage, l, start, now, bucket, rollup).await?;
+    }
+    if let Some(m) = &sources.motion {
+        input.occupancy = load_motion(&storage, m, start, now, bucket, rollup).await?;
+        input.motions = m.read(&storage, raw_since, now).await?;
+    }
+
+    let (days, records) = compute(&input, &time_zone, first_day, raw_since, now);
+    let stats = SensorStats {
+        name: sensor.name.clone(),
+        days,
+        records,
+        raw_since,
+    };
+    STATS_CACHE.lock().await.insert(key, (now, stats.clone()));
+    Ok(stats)
+}
+
+fn format_minutes(minutes: f64) -> String {
+    let minutes = minutes.round() as i64;
+    if minutes >= 60 {
+        format!("{}h {}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{} min", minutes)
+    }
+}
+
+fn record_label(kind: RecordKind) -> &'static str {
+    match kind {
+        RecordKind::ColdestNight => "Coldest night this winter",
+        RecordKind::WarmestDay => "Warmest reading",
+        RecordKind::LongestDaylight => "Most daylight",
+        RecordKind::MostActiveDay => "Busiest day",
+        RecordKind::LongestMotion => "Longest continuous motion",
+    }
+}
+
+fn record_value(prefs: &Preferences, record: &StatRecord) -> String {
+    match record.kind {
+        RecordKind::ColdestNight | RecordKind::WarmestDay => prefs.format_temperature(record.value),
+        RecordKind::LongestDaylight => format!("{:.1} h", record.value),
+        RecordKind::MostActiveDay | RecordKind::LongestMotion => format_minutes(record.value),
+    }
+}
+
+/// The Stats page component that will be rendered when the current route is `[Route::Stats]`
+#[component]
+pub fn Stats(sensor_id: String) -> Element {
+    let prefs_signal = use_preferences();
+    let prefs = prefs_signal();
+    let mut days = use_signal(|| 30);
+    let graphs_id = sensor_id.clone();
+
+    let stats = use_resource(use_reactive!(|sensor_id| async move {
+        let time_zone = prefs_signal.read().export_time_zone();
+        get_sensor_stats(sensor_id, days(), time_zone).await
+    }));
+
+    let choice_class = |active: bool| {
+        if active {
+            "px-3 py-1 rounded bg-blue-600 text-white text-sm"
+        } else {
+            "px-3 py-1 rounded bg-gray-100 dark:bg-gray-800 hover:bg-gray-200 dark:hover:bg-gray-700 text-sm"
+        }
+    };
+    let title = match &*stats.read() {
+        Some(Ok(stats)) => format!("Daily stats for {}", stats.name),
+        _ => "Daily stats".to_string(),
+    };
+    let date = |d: chrono::NaiveDate| d.format(prefs.date_format.pattern()).to_string();
+
+    rsx! {
+        div { class: "w-full p-4 pb-20 max-w-[100vw] overflow-x-hidden",
+            div { class: "max-w-5xl mx-auto",
+                div { class: "flex items-center gap-4 mb-6",
+                    Link {
+                        to: Route::Graphs { sensor_id: graphs_id, range: Default::default() },
+                        class: "p-2 rounded-full hover:bg-gray-100 dark:hover:bg-gray-800 transition-colors",
+                        "←"
+                    }
+                    h1 { class: "text-2xl font-bold", "{title}" }
+                }
+                div { class: "flex flex-wrap gap-2 mb-6",
+                    for (value, label) in STATS_DAYS {
+                        button {
+                            class: choice_class(days() == value),
+                            onclick: move |_| days.set(value),
+                            "{label}"
+                        }
+                    }
+                }
+                {
+                    match &*stats.read() {
+                        Some(Ok(stats)) => rsx! {
+                            if !stats.records.is_empty() {
+                                div { class: "grid grid-cols-2 md:grid-cols-3 gap-4 mb-8",
+                                    for record in stats.records.iter() {
+                                        div { class: "p-4 bg-white dark:bg-gray-800 rounded-lg shadow",
+                                            div { class: "text-sm text-gray-500", "{record_label(record.kind)}" }
+                                            div { class: "text-xl font-semibold", "{record_value(&prefs, record)}" }
+                                            div { class: "text-xs text-gray-400", "{date(record.date)}" }
+                                        }
+                                    }
+                                }
+                            }
+                            DailyTable { days: stats.days.clone() }
+                            p { class: "text-xs text-gray-400 mt-2",
+                                "Motion events are counted from raw readings, kept since {prefs.format_datetime(stats.raw_since)}."
+                            }
+                        },
+                        Some(Err(e)) => rsx! {
+                            div { class: "p-4 rounded-lg border border-amber-300 bg-amber-50 dark:bg-amber-900/30 dark:border-amber-700 text-amber-800 dark:text-amber-200",
+                                h2 { class: "font-semibold mb-1", "No statistics to show" }
+                                p { class: "text-sm", "{e}" }
+                            }
+                        },
+                        None => rsx! {
+                            div { class: "text-gray-400 italic", "Loading…" }
+                        },
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// One row per day, newest first
+#[component]
+fn DailyTable(days: Vec<DailyStats>) -> Element {
+    let prefs = use_preferences()();
+    let temperature = |v: Option<f64>| v.map(|v| prefs.format_temperature(v)).unwrap_or_else(|| "–".to_string());
+
+    rsx! {
+        div { class: "bg-white dark:bg-gray-800 rounded-lg shadow overflow-x-auto",
+            table { class: "w-full text-sm",
+                thead {
+                    tr { class: "text-left text-gray-500",
+                        th { class: "p-2 font-normal", "Date" }
+                        th { class: "p-2 font-normal", "Min" }
+                        th { class: "p-2 font-normal", "Mean" }
+                        th { class: "p-2 font-normal", "Max" }
+                        th { class: "p-2 font-normal", "Daylight" }
+                        th { class: "p-2 font-normal", "Motion events" }
+                        th { class: "p-2 font-normal", "Occupied" }
+                    }
+                }
+                tbody {
+                    for day in days.iter().rev() {
+                        tr { class: "border-t border-gray-100 dark:border-gray-700",
+                            td { class: "p-2 whitespace-nowrap", "{day.date.format(prefs.date_format.pattern())}" }
+                            td { class: "p-2", "{temperature(day.min_temperature)}" }
+                            td { class: "p-2", "{temperature(day.mean_temperature)}" }
+                            td { class: "p-2", "{temperature(day.max_temperature)}" }
+                            td { class: "p-2",
+                                {day.daylight_hours.map(|h| format!("{:.1} h", h)).unwrap_or_else(|| "–".to_string())}
+                            }
+                            td { class: "p-2",
+                                {day.motion_events.map(|n| n.to_string()).unwrap_or_else(|| "–".to_string())}
+                            }
+                            td { class: "p-2",
+                                {day.occupied_minutes.map(format_minutes).unwrap_or_else(|| "–".to_string())}
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}